@@ -0,0 +1,135 @@
+//! Measures how many samples per second make it from the input to the store while the render loop
+//! is busy drawing frames.
+//!
+//! Run with `cargo bench --bench ingest`.
+
+#[path = "../src/rt-plot/binary.rs"]
+mod binary;
+#[path = "../src/rt-plot/calibration.rs"]
+mod calibration;
+#[path = "../src/rt-plot/clock.rs"]
+mod clock;
+#[path = "../src/rt-plot/config.rs"]
+#[allow(dead_code)]
+mod config;
+#[path = "../src/rt-plot/expression.rs"]
+mod expression;
+#[path = "../src/rt-plot/filter.rs"]
+mod filter;
+#[path = "../src/rt-plot/ingest.rs"]
+mod ingest;
+#[path = "../src/rt-plot/input.rs"]
+mod input;
+#[path = "../src/rt-plot/source.rs"]
+#[allow(dead_code)]
+mod source;
+#[path = "../src/rt-plot/status.rs"]
+#[allow(dead_code)]
+mod status;
+#[path = "../src/rt-plot/store.rs"]
+#[allow(dead_code)]
+mod store;
+
+use std::io::Cursor;
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLES: usize = 2_000_000;
+const CHANNELS: usize = 3;
+
+// Time the simulated render loop spends on every frame, as if it were drawing at 60 FPS.
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+fn data_config() -> config::DataConfig {
+    let mut data_config = String::from(
+        r#"
+        [grid]
+        label = "Benchmark"
+
+        [grid.time]
+        divisions = 6
+        seconds_per_division = 1.0
+        raw_per_second = 1000000.0
+        label = "1 div = 1 s"
+
+        [grid.data]
+        divisions = 4
+        zero_shift = 0.0
+        label = "1 div = 1"
+        "#,
+    );
+
+    for _ in 0..CHANNELS {
+        data_config.push_str("[[y]]\nraw_offset = 0.0\nraw_per_division = 1000.0\n");
+    }
+
+    toml::from_str(&data_config).unwrap()
+}
+
+fn input() -> Vec<u8> {
+    let mut input = String::new();
+
+    for i in 0..SAMPLES {
+        input.push_str(&format!("{}", i * 20));
+        for channel in 0..CHANNELS {
+            input.push_str(&format!(
+                " {:.3}",
+                ((i + channel) as f32 / 100.0).sin() * 1000.0
+            ));
+        }
+        input.push('\n');
+    }
+
+    input.into_bytes()
+}
+
+fn main() {
+    let data_config = Arc::new(data_config());
+    let input = input();
+
+    let status = Arc::new(status::Status::new());
+    let pipeline = ingest::Pipeline::new(data_config.clone(), status, None);
+    let (sender, receiver) = mpsc::channel();
+
+    let started = Instant::now();
+
+    let input_thread = thread::spawn(move || {
+        let source = Box::new(source::ReaderSource::new(Cursor::new(input)));
+        ingest::run(source, pipeline, sender, Arc::new(AtomicBool::new(false)));
+        started.elapsed()
+    });
+
+    // Simulate the render loop, which drains the channel once per frame and is otherwise busy.
+    let mut data = store::Store::new(&data_config);
+    let mut frames = 0;
+    let mut received = 0;
+
+    while received < SAMPLES {
+        for chunk in receiver.try_iter() {
+            received += chunk.len();
+            data.extend(chunk);
+        }
+
+        thread::sleep(FRAME_TIME);
+        frames += 1;
+    }
+
+    let ingest_time = input_thread.join().unwrap();
+    let total_time = started.elapsed();
+
+    println!(
+        "Ingested {} samples with {} channels in {:.3} s ({:.0} samples/s).",
+        SAMPLES,
+        CHANNELS,
+        ingest_time.as_secs_f64(),
+        SAMPLES as f64 / ingest_time.as_secs_f64()
+    );
+    println!(
+        "Stored all of them after {} frames in {:.3} s ({:.0} samples/s).",
+        frames,
+        total_time.as_secs_f64(),
+        SAMPLES as f64 / total_time.as_secs_f64()
+    );
+}
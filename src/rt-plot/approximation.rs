@@ -1,7 +1,8 @@
+use super::config::Y;
+use super::store::Store;
 use ndarray::*;
 use ndarray_linalg::*;
 use ordered_float::OrderedFloat;
-use std::collections::BTreeMap;
 
 #[derive(Debug)]
 pub enum Type {
@@ -60,7 +61,7 @@ fn mse(ground: &Vec<f32>, predicted: &Vec<f32>, dof: usize) -> f32 {
 }
 
 pub fn fit(
-    data: &BTreeMap<OrderedFloat<f32>, Vec<f32>>,
+    data: &Store,
     range: &std::ops::Range<OrderedFloat<f32>>,
     approximation_type: &Type,
     channel: usize,
@@ -69,6 +70,11 @@ pub fn fit(
     let mut y = vec![];
 
     for (time, data) in data.range(range.clone()) {
+        // Skip gaps in the channel.
+        if data[channel].is_nan() {
+            continue;
+        }
+
         x.push(time);
         y.push(OrderedFloat(data[channel]));
     }
@@ -216,7 +222,40 @@ pub fn transform_coefficients(
     }
 }
 
-pub fn measurement_to_string(value: f32, error: f32) -> String {
+/// Converts transformed coefficients and their errors from grid units to seconds and the units of
+/// the channel.
+pub fn to_physical_units(
+    coefficients: &Vec<f32>,
+    errors: &Vec<f32>,
+    approximation_type: &Type,
+    y: &Y,
+    seconds_per_division: f32,
+) -> (Vec<f32>, Vec<f32>) {
+    let (r, o, s) = (y.raw_per_division, y.raw_offset, seconds_per_division);
+
+    // Values of the channel are offset, while times and rates of change are only scaled.
+    let (scales, offsets) = match approximation_type {
+        Type::Constant => (vec![r], vec![o]),
+        Type::Linear => (vec![s, r / s], vec![0.0, 0.0]),
+        Type::Quadratic => (vec![r, s, r / (s * s)], vec![o, 0.0, 0.0]),
+    };
+
+    (
+        coefficients
+            .iter()
+            .zip(&scales)
+            .zip(&offsets)
+            .map(|((coefficient, scale), offset)| coefficient * scale + offset)
+            .collect(),
+        errors
+            .iter()
+            .zip(&scales)
+            .map(|(error, scale)| (error * scale).abs())
+            .collect(),
+    )
+}
+
+pub fn measurement_to_string(value: f32, error: f32, unit: &str) -> String {
     let lambda = error.log10().round() as i64;
     let nd = {
         if lambda < 0 {
@@ -226,5 +265,8 @@ pub fn measurement_to_string(value: f32, error: f32) -> String {
         }
     };
 
-    format!("{:.nd$} ± {:.nd$}", value, error, nd = nd)
+    match unit {
+        "" => format!("{:.nd$} ± {:.nd$}", value, error, nd = nd),
+        unit => format!("({:.nd$} ± {:.nd$}) {}", value, error, unit, nd = nd),
+    }
 }
@@ -0,0 +1,212 @@
+use super::config::DataConfig;
+use super::plot::Pane;
+use super::store::Store;
+use nalgebra::{Matrix3, Vector2};
+use ordered_float::OrderedFloat;
+
+// How many of the most recent samples autoset looks at.
+const RECENT_SAMPLES: usize = 100_000;
+// How much of the height of the grid a channel fills.
+const FILL: f64 = 0.8;
+// How many periods of a periodic signal are shown.
+const PERIODS: f64 = 3.0;
+
+// Rounds up to the nearest 1, 2 or 5 times a power of ten, like the scales of an oscilloscope.
+fn round_up(value: f64) -> f64 {
+    let magnitude = 10f64.powf(value.log10().floor());
+
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|multiplier| multiplier * magnitude)
+        .find(|&scale| scale >= value)
+        .unwrap()
+}
+
+/// The scales the plot is drawn with. They start out as set in the data config and are changed by
+/// autoset, while samples stay stored in the grid units of the data config.
+pub struct Scales {
+    pub seconds_per_division: f32,
+    pub raw_per_division: Vec<f32>,
+    pub raw_offset: Vec<f32>,
+}
+
+impl Scales {
+    pub fn new(data_config: &DataConfig) -> Scales {
+        Scales {
+            seconds_per_division: data_config.grid.time.seconds_per_division,
+            raw_per_division: data_config.y.iter().map(|y| y.raw_per_division).collect(),
+            raw_offset: data_config.y.iter().map(|y| y.raw_offset).collect(),
+        }
+    }
+
+    /// Width of the plot in the grid units of the data config.
+    pub fn time_span(&self, data_config: &DataConfig) -> f32 {
+        data_config.grid.time.divisions as f32 * self.seconds_per_division
+            / data_config.grid.time.seconds_per_division
+    }
+
+    // Scale and offset that take the values of a channel from the grid units of the data config to
+    // these scales.
+    fn channel_scale(&self, data_config: &DataConfig, channel: usize) -> (f32, f32) {
+        let y = &data_config.y[channel];
+
+        (
+            y.raw_per_division / self.raw_per_division[channel],
+            (y.raw_offset - self.raw_offset[channel]) / self.raw_per_division[channel],
+        )
+    }
+
+    /// Transforms the values of a channel from the grid units of the data config to these scales.
+    pub fn channel_transform(&self, data_config: &DataConfig, channel: usize) -> Matrix3<f32> {
+        let (scale, offset) = self.channel_scale(data_config, channel);
+
+        Matrix3::new_translation(&Vector2::new(0.0, offset))
+            * Matrix3::new_nonuniform_scaling(&Vector2::new(1.0, scale))
+    }
+
+    /// Like `channel_transform`, but for the channels on both axes in XY mode.
+    pub fn xy_transform(&self, data_config: &DataConfig, x: usize, y: usize) -> Matrix3<f32> {
+        let (x_scale, x_offset) = self.channel_scale(data_config, x);
+        let (y_scale, y_offset) = self.channel_scale(data_config, y);
+
+        Matrix3::new_translation(&Vector2::new(x_offset, y_offset))
+            * Matrix3::new_nonuniform_scaling(&Vector2::new(x_scale, y_scale))
+    }
+
+    pub fn time_label(&self) -> String {
+        format!("{} s/div", self.seconds_per_division)
+    }
+
+    pub fn channel_label(&self, data_config: &DataConfig, channel: usize) -> String {
+        let y = &data_config.y[channel];
+        let name = y
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("y{}", channel + 1));
+
+        match &y.unit {
+            Some(unit) => format!("{}: {} {}/div", name, self.raw_per_division[channel], unit),
+            None => format!("{}: {}/div", name, self.raw_per_division[channel]),
+        }
+    }
+
+    pub fn data_label(&self, data_config: &DataConfig, channels: &[usize]) -> String {
+        channels
+            .iter()
+            .map(|&i| self.channel_label(data_config, i))
+            .collect::<Vec<String>>()
+            .join("   ")
+    }
+}
+
+/// Picks scales from the most recent samples so that every channel fills its pane around its
+/// center, and a few periods of the given channel are visible if it's periodic.
+pub fn autoset(
+    data: &Store,
+    data_config: &DataConfig,
+    panes: &[Pane],
+    scales: &Scales,
+    channel: usize,
+) -> Scales {
+    let seconds_per_grid_unit = data_config.grid.time.seconds_per_division as f64;
+    let channels = data_config.y.len();
+
+    // Samples in seconds and the calibrated units of the channels, oldest first.
+    let mut samples: Vec<(f64, Vec<f64>)> = data
+        .range(OrderedFloat(f32::NEG_INFINITY)..OrderedFloat(f32::INFINITY))
+        .rev()
+        .take(RECENT_SAMPLES)
+        .map(|(time, values)| {
+            let values = (0..channels)
+                .map(|i| {
+                    let y = &data_config.y[i];
+                    values[i] as f64 * y.raw_per_division as f64 + y.raw_offset as f64
+                })
+                .collect();
+
+            (time.into_inner() as f64 * seconds_per_grid_unit, values)
+        })
+        .collect();
+    samples.reverse();
+
+    let mut raw_per_division = scales.raw_per_division.clone();
+    let mut raw_offset = scales.raw_offset.clone();
+    let mut seconds_per_division = scales.seconds_per_division;
+
+    for i in 0..channels {
+        let values = samples
+            .iter()
+            .map(|(_, values)| values[i])
+            .filter(|v| !v.is_nan());
+        let min = values.clone().fold(f64::INFINITY, f64::min);
+        let max = values.fold(f64::NEG_INFINITY, f64::max);
+
+        // Flat or missing channels keep their scale.
+        if !(max > min) {
+            continue;
+        }
+
+        // Every channel is in a pane, and it is fitted to the first one it is in.
+        let pane = panes
+            .iter()
+            .find(|pane| pane.channels.contains(&i))
+            .unwrap();
+        let (divisions, zero_shift) = (pane.divisions, pane.zero_shift);
+
+        let scale = round_up((max - min) / (divisions as f64 * FILL));
+        let middle = (max + min) / 2.0;
+
+        raw_per_division[i] = scale as f32;
+        // Puts the middle of the channel in the middle of its pane.
+        raw_offset[i] = (middle + zero_shift as f64 * scale) as f32;
+    }
+
+    if let Some(period) = estimate_period(&samples, channel) {
+        seconds_per_division =
+            round_up(PERIODS * period / data_config.grid.time.divisions as f64) as f32;
+    }
+
+    Scales {
+        seconds_per_division,
+        raw_per_division,
+        raw_offset,
+    }
+}
+
+// Estimates the period of a channel from the times it rises through the middle of its range. The
+// hysteresis keeps noise from counting as crossings.
+fn estimate_period(samples: &[(f64, Vec<f64>)], channel: usize) -> Option<f64> {
+    let values = samples
+        .iter()
+        .map(|(_, values)| values[channel])
+        .filter(|v| !v.is_nan());
+    let min = values.clone().fold(f64::INFINITY, f64::min);
+    let max = values.fold(f64::NEG_INFINITY, f64::max);
+
+    if !(max > min) {
+        return None;
+    }
+
+    let middle = (max + min) / 2.0;
+    let hysteresis = (max - min) * 0.1;
+
+    let mut armed = false;
+    let mut crossings = vec![];
+    for (time, values) in samples {
+        let value = values[channel];
+
+        if value < middle - hysteresis {
+            armed = true;
+        } else if armed && value > middle {
+            crossings.push(*time);
+            armed = false;
+        }
+    }
+
+    // At least two full periods, so that a single step isn't mistaken for one.
+    if crossings.len() < 3 {
+        return None;
+    }
+
+    Some((crossings[crossings.len() - 1] - crossings[0]) / (crossings.len() - 1) as f64)
+}
@@ -0,0 +1,299 @@
+use super::config::{Crc, Endianness, FieldType, Layout};
+use super::input::ParseError;
+use std::ops::Range;
+
+impl FieldType {
+    fn size(&self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+        }
+    }
+}
+
+impl Crc {
+    fn size(&self) -> usize {
+        match self {
+            Crc::Crc8 => 1,
+            Crc::Crc16 | Crc::Crc16Modbus => 2,
+            Crc::Crc32 => 4,
+        }
+    }
+
+    fn compute(&self, bytes: &[u8]) -> u64 {
+        match self {
+            // CRC-8 with polynomial 0x07, as used by SMBus.
+            Crc::Crc8 => bytes.iter().fold(0u8, |mut crc, &byte| {
+                crc ^= byte;
+                for _ in 0..8 {
+                    crc = if crc & 0x80 != 0 {
+                        (crc << 1) ^ 0x07
+                    } else {
+                        crc << 1
+                    };
+                }
+                crc
+            }) as u64,
+            // CRC-16/CCITT-FALSE.
+            Crc::Crc16 => bytes.iter().fold(0xFFFFu16, |mut crc, &byte| {
+                crc ^= (byte as u16) << 8;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 {
+                        (crc << 1) ^ 0x1021
+                    } else {
+                        crc << 1
+                    };
+                }
+                crc
+            }) as u64,
+            Crc::Crc16Modbus => bytes.iter().fold(0xFFFFu16, |mut crc, &byte| {
+                crc ^= byte as u16;
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ 0xA001
+                    } else {
+                        crc >> 1
+                    };
+                }
+                crc
+            }) as u64,
+            // The CRC-32 used by Ethernet, zlib and most microcontroller peripherals.
+            Crc::Crc32 => !bytes.iter().fold(0xFFFF_FFFFu32, |mut crc, &byte| {
+                crc ^= byte as u32;
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ 0xEDB8_8320
+                    } else {
+                        crc >> 1
+                    };
+                }
+                crc
+            }) as u64,
+        }
+    }
+}
+
+/// Reads an unsigned integer of any width up to 64 bits.
+pub fn read_unsigned(bytes: &[u8], endianness: Endianness) -> u64 {
+    let fold = |value: u64, &byte: &u8| (value << 8) | byte as u64;
+
+    match endianness {
+        Endianness::Little => bytes.iter().rev().fold(0, fold),
+        Endianness::Big => bytes.iter().fold(0, fold),
+    }
+}
+
+/// Splits a stream of bytes into frames of packed binary records and decodes them. A frame is an
+/// optional sync word, followed by the fields of the layout and an optional CRC of the fields.
+pub struct Decoder {
+    fields: Vec<FieldType>,
+    endianness: Endianness,
+    sync: Vec<u8>,
+    crc: Option<Crc>,
+    payload_length: usize,
+    frame_length: usize,
+}
+
+impl Decoder {
+    /// Creates a decoder for the layout, which has to declare at least the `columns` used.
+    pub fn new(layout: &Layout, columns: usize) -> Decoder {
+        let fields = layout.fields.clone();
+
+        assert!(
+            !fields.is_empty(),
+            "Binary input requires the fields of the layout to be declared."
+        );
+        assert!(
+            fields.len() >= columns,
+            "The binary layout declares {} fields, but {} columns are used.",
+            fields.len(),
+            columns
+        );
+
+        let sync = layout.sync.clone().unwrap_or_default();
+        let payload_length: usize = fields.iter().map(|field| field.size()).sum();
+        let frame_length = sync.len() + payload_length + layout.crc.map_or(0, |crc| crc.size());
+
+        Decoder {
+            fields,
+            endianness: layout.endianness,
+            sync,
+            crc: layout.crc,
+            payload_length,
+            frame_length,
+        }
+    }
+
+    /// Finds the next frame in `bytes`. Returns the range of its fields, or the reason it was
+    /// rejected, along with the number of bytes consumed. Returns `None` if more bytes are needed.
+    pub fn next_frame(&self, bytes: &[u8]) -> Option<(Result<Range<usize>, ParseError>, usize)> {
+        // Skip anything before the sync word, which happens after corrupted frames.
+        if !self.sync.is_empty() {
+            match bytes
+                .windows(self.sync.len())
+                .position(|window| window == &self.sync[..])
+            {
+                Some(0) => {}
+                Some(start) => return Some((Err(ParseError::Sync), start)),
+                // Keep the tail, which might be the start of the sync word.
+                None if bytes.len() >= self.sync.len() => {
+                    return Some((Err(ParseError::Sync), bytes.len() - self.sync.len() + 1))
+                }
+                None => return None,
+            }
+        }
+
+        if bytes.len() < self.frame_length {
+            return None;
+        }
+
+        let payload = self.sync.len()..self.sync.len() + self.payload_length;
+
+        if let Some(crc) = self.crc {
+            let expected = read_unsigned(&bytes[payload.end..self.frame_length], self.endianness);
+
+            // Move on by a single byte, so that a real frame starting inside this one isn't lost.
+            if crc.compute(&bytes[payload.clone()]) != expected {
+                return Some((Err(ParseError::Crc), 1));
+            }
+        }
+
+        Some((Ok(payload), self.frame_length))
+    }
+
+    /// Decodes the fields of a frame.
+    pub fn decode(&self, payload: &[u8]) -> Vec<f64> {
+        let mut start = 0;
+
+        self.fields
+            .iter()
+            .map(|field| {
+                let bytes = &payload[start..start + field.size()];
+                start += field.size();
+
+                let value = read_unsigned(bytes, self.endianness);
+                match field {
+                    FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => {
+                        value as f64
+                    }
+                    FieldType::I8 => value as u8 as i8 as f64,
+                    FieldType::I16 => value as u16 as i16 as f64,
+                    FieldType::I32 => value as u32 as i32 as f64,
+                    FieldType::I64 => value as i64 as f64,
+                    FieldType::F32 => f32::from_bits(value as u32) as f64,
+                    FieldType::F64 => f64::from_bits(value),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(fields: Vec<FieldType>, sync: Option<Vec<u8>>, crc: Option<Crc>) -> Layout {
+        Layout {
+            fields,
+            endianness: Endianness::Little,
+            sync,
+            crc,
+        }
+    }
+
+    // Splits bytes into frames the way the input thread does, keeping the payloads.
+    fn frames(decoder: &Decoder, bytes: &[u8]) -> Vec<Result<Vec<u8>, ParseError>> {
+        let mut frames = vec![];
+        let mut start = 0;
+
+        while let Some((frame, length)) = decoder.next_frame(&bytes[start..]) {
+            frames.push(
+                frame.map(|payload| bytes[start + payload.start..start + payload.end].to_vec()),
+            );
+            start += length;
+        }
+
+        frames
+    }
+
+    #[test]
+    fn crc_check_values() {
+        let check = b"123456789";
+
+        assert_eq!(Crc::Crc8.compute(check), 0xF4);
+        assert_eq!(Crc::Crc16.compute(check), 0x29B1);
+        assert_eq!(Crc::Crc16Modbus.compute(check), 0x4B37);
+        assert_eq!(Crc::Crc32.compute(check), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn decodes_fields() {
+        let decoder = Decoder::new(
+            &Layout {
+                endianness: Endianness::Big,
+                ..layout(
+                    vec![FieldType::U16, FieldType::I8, FieldType::F32],
+                    None,
+                    None,
+                )
+            },
+            3,
+        );
+
+        let mut bytes = vec![0x12, 0x34, 0xFE];
+        bytes.extend_from_slice(&1.5f32.to_be_bytes());
+
+        assert_eq!(frames(&decoder, &bytes), vec![Ok(bytes.clone())]);
+        assert_eq!(decoder.decode(&bytes), vec![4660.0, -2.0, 1.5]);
+    }
+
+    #[test]
+    fn resynchronizes_after_corrupted_frame() {
+        let decoder = Decoder::new(
+            &layout(
+                vec![FieldType::U16, FieldType::U16],
+                Some(vec![0xAA, 0x55]),
+                Some(Crc::Crc16),
+            ),
+            2,
+        );
+
+        let frame = |payload: [u8; 4]| {
+            let mut frame = vec![0xAA, 0x55];
+            frame.extend_from_slice(&payload);
+            frame.extend_from_slice(&(Crc::Crc16.compute(&payload) as u16).to_le_bytes());
+            frame
+        };
+
+        let mut corrupted = frame([1, 2, 3, 4]);
+        corrupted[3] ^= 0xFF;
+
+        // Garbage before the first frame, a corrupted frame and then a valid one.
+        let mut bytes = vec![0x00, 0x13];
+        bytes.extend(corrupted);
+        bytes.extend(frame([5, 6, 7, 8]));
+
+        assert_eq!(
+            frames(&decoder, &bytes),
+            vec![
+                Err(ParseError::Sync),
+                Err(ParseError::Crc),
+                Err(ParseError::Sync),
+                Ok(vec![5, 6, 7, 8]),
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_incomplete_frames() {
+        let decoder = Decoder::new(
+            &layout(vec![FieldType::U32], Some(vec![0xAA, 0x55]), None),
+            1,
+        );
+
+        assert_eq!(frames(&decoder, &[0xAA]), vec![]);
+        assert_eq!(frames(&decoder, &[0xAA, 0x55, 1, 2]), vec![]);
+    }
+}
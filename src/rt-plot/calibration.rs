@@ -0,0 +1,98 @@
+use super::config::Transform;
+
+impl Transform {
+    /// Turns a raw value into a physical one. Values outside of a table are clamped to its ends.
+    pub fn apply(&self, raw: f64) -> f64 {
+        match self {
+            Transform::Polynomial { coefficients } => coefficients
+                .iter()
+                .rev()
+                .fold(0.0, |value, coefficient| value * raw + coefficient),
+            Transform::Table { points } => {
+                // Keep gaps in the channel.
+                if raw.is_nan() {
+                    return raw;
+                }
+
+                // The table is validated to be either increasing or decreasing.
+                let increasing = points[0][0] < points[1][0];
+                let next = points.iter().position(|point| match increasing {
+                    true => point[0] > raw,
+                    false => point[0] < raw,
+                });
+
+                match next {
+                    Some(0) => points[0][1],
+                    Some(i) => {
+                        let (from, to) = (points[i - 1], points[i]);
+                        from[1] + (raw - from[0]) * (to[1] - from[1]) / (to[0] - from[0])
+                    }
+                    None => points[points.len() - 1][1],
+                }
+            }
+            Transform::SteinhartHart { a, b, c } => {
+                let ln = raw.ln();
+                1.0 / (a + b * ln + c * ln.powi(3))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(points: &[[f64; 2]]) -> Transform {
+        Transform::Table {
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn evaluates_polynomials_from_the_constant_term() {
+        let transform = Transform::Polynomial {
+            coefficients: vec![1.0, -2.0, 0.5],
+        };
+
+        assert_eq!(transform.apply(0.0), 1.0);
+        assert_eq!(transform.apply(2.0), -1.0);
+        assert_eq!(transform.apply(-4.0), 17.0);
+    }
+
+    #[test]
+    fn interpolates_increasing_tables() {
+        let transform = table(&[[0.0, 10.0], [10.0, 20.0], [20.0, 0.0]]);
+
+        assert_eq!(transform.apply(5.0), 15.0);
+        assert_eq!(transform.apply(10.0), 20.0);
+        assert_eq!(transform.apply(15.0), 10.0);
+        assert_eq!(transform.apply(-5.0), 10.0);
+        assert_eq!(transform.apply(25.0), 0.0);
+        assert!(transform.apply(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn interpolates_decreasing_tables() {
+        let transform = table(&[[20.0, 0.0], [10.0, 20.0], [0.0, 10.0]]);
+
+        assert_eq!(transform.apply(15.0), 10.0);
+        assert_eq!(transform.apply(10.0), 20.0);
+        assert_eq!(transform.apply(5.0), 15.0);
+        assert_eq!(transform.apply(25.0), 0.0);
+        assert_eq!(transform.apply(-5.0), 10.0);
+    }
+
+    #[test]
+    fn turns_resistance_into_temperature() {
+        // A common 10 kΩ thermistor, which has that resistance at 25 °C.
+        let transform = Transform::SteinhartHart {
+            a: 1.129148e-3,
+            b: 2.34125e-4,
+            c: 8.76741e-8,
+        };
+
+        assert!((transform.apply(10_000.0) - 298.15).abs() < 0.05);
+        // Higher resistances are colder.
+        assert!(transform.apply(30_000.0) < 278.15);
+    }
+}
@@ -0,0 +1,141 @@
+use super::config::{Time, Timestamps};
+use super::status::Status;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Produces the timestamp of every sample in raw units, either by taking it from the input or by
+/// synthesizing it.
+pub struct Clock {
+    timestamps: Timestamps,
+    raw_per_second: f64,
+    sample_rate: f64,
+    samples: u64,
+    started: Option<Instant>,
+
+    // Unwrapping of hardware counters and handling of device resets.
+    modulus: Option<f64>,
+    reset_threshold: f64,
+    offset: f64,
+    last_raw: Option<f64>,
+    last_timestamp: Option<f64>,
+    last_interval: f64,
+
+    status: Arc<Status>,
+}
+
+impl Clock {
+    pub fn new(time: &Time, status: Arc<Status>) -> Clock {
+        Clock {
+            timestamps: time.timestamps,
+            raw_per_second: time.raw_per_second as f64,
+            sample_rate: time.sample_rate.unwrap_or(1.0) as f64,
+            samples: 0,
+            started: None,
+            modulus: time.counter_bits.map(|bits| 2f64.powi(bits as i32)),
+            // By default, anything more than a second backwards is considered a reset.
+            reset_threshold: time.reset_threshold.unwrap_or(time.raw_per_second as f64),
+            offset: 0.0,
+            last_raw: None,
+            last_timestamp: None,
+            last_interval: 0.0,
+            status,
+        }
+    }
+
+    /// Turns a timestamp read from the input into a monotonically increasing one by accounting for
+    /// counters that wrap around and for devices that reset.
+    fn unwrap(&mut self, raw: f64) -> f64 {
+        // The counter wrapped around if it went from near the top of its range to near zero, so
+        // that less than the reset threshold passed across the wrap. Any other backwards jump is
+        // a reset, which is handled below.
+        if let (Some(modulus), Some(last_raw)) = (self.modulus, self.last_raw) {
+            if last_raw - raw > modulus / 2.0 && modulus - last_raw + raw <= self.reset_threshold {
+                self.offset += modulus;
+            }
+        }
+        self.last_raw = Some(raw);
+
+        let mut timestamp = raw + self.offset;
+
+        if let Some(last_timestamp) = self.last_timestamp {
+            // A large backwards jump means the device was reset. Start a new segment right after
+            // the last sample so that it doesn't end up on top of older data.
+            if last_timestamp - timestamp > self.reset_threshold {
+                self.offset += last_timestamp - timestamp + self.last_interval;
+                timestamp = last_timestamp + self.last_interval;
+
+                self.status.record_reset();
+            }
+
+            if timestamp > last_timestamp {
+                self.last_interval = timestamp - last_timestamp;
+            }
+        }
+
+        // Small backwards jumps are kept as they are, and the last timestamp never decreases.
+        self.last_timestamp = Some(match self.last_timestamp {
+            Some(last_timestamp) => last_timestamp.max(timestamp),
+            None => timestamp,
+        });
+
+        timestamp
+    }
+
+    /// Returns the timestamp of the next sample in raw units, given the timestamp read from the
+    /// input if there is one.
+    pub fn timestamp(&mut self, time: Option<f64>) -> f64 {
+        let timestamp = match self.timestamps {
+            Timestamps::Input => self.unwrap(time.unwrap()),
+            Timestamps::SampleRate => self.samples as f64 / self.sample_rate * self.raw_per_second,
+            Timestamps::Arrival => {
+                let started = self.started.get_or_insert_with(Instant::now);
+                started.elapsed().as_secs_f64() * self.raw_per_second
+            }
+        };
+
+        self.samples += 1;
+
+        timestamp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter_clock(bits: u32, reset_threshold: f64) -> Clock {
+        let time = Time {
+            divisions: 10,
+            seconds_per_division: 1.0,
+            raw_per_second: 1000.0,
+            timestamps: Timestamps::Input,
+            sample_rate: None,
+            counter_bits: Some(bits),
+            reset_threshold: Some(reset_threshold),
+            label: String::new(),
+        };
+
+        Clock::new(&time, Arc::new(Status::new()))
+    }
+
+    #[test]
+    fn unwraps_counter_overflow() {
+        let mut clock = counter_clock(16, 1000.0);
+
+        assert_eq!(clock.timestamp(Some(65000.0)), 65000.0);
+        assert_eq!(clock.timestamp(Some(65500.0)), 65500.0);
+        assert_eq!(clock.timestamp(Some(464.0)), 66000.0);
+    }
+
+    #[test]
+    fn treats_reset_in_upper_half_as_reset() {
+        let mut clock = counter_clock(16, 1000.0);
+
+        assert_eq!(clock.timestamp(Some(39000.0)), 39000.0);
+        assert_eq!(clock.timestamp(Some(40000.0)), 40000.0);
+        // The new segment continues right after the last sample instead of jumping ahead by the
+        // rest of the counter range.
+        assert_eq!(clock.timestamp(Some(0.0)), 41000.0);
+        assert_eq!(clock.timestamp(Some(1000.0)), 42000.0);
+    }
+}
@@ -17,11 +17,33 @@ pub struct ColorScheme {
     pub channel: Vec<Color>,
 }
 
+/// Where the timestamp of each sample comes from.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Timestamps {
+    Input,
+    SampleRate,
+    Arrival,
+}
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Timestamps::Input
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Time {
     pub divisions: u32,
     pub seconds_per_division: f32,
     pub raw_per_second: f32,
+    #[serde(default)]
+    pub timestamps: Timestamps,
+    pub sample_rate: Option<f32>,
+    // Width in bits of a hardware counter used as the timestamp, which wraps around to zero.
+    pub counter_bits: Option<u32>,
+    // Backwards jump in raw units that is treated as a reset of the device.
+    pub reset_threshold: Option<f64>,
     pub label: String,
 }
 
@@ -33,27 +55,450 @@ pub struct Data {
     pub label: String,
 }
 
+/// A grid stacked with others, in which some of the channels are drawn with its own vertical scale.
+/// All of them share the time axis.
+#[derive(Deserialize, Debug)]
+pub struct Pane {
+    // Names of the channels, or y1, y2 and so on.
+    pub channels: Vec<String>,
+    pub divisions: u32,
+    #[serde(default)]
+    pub zero_shift: f32,
+    #[serde(default)]
+    pub label: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Grid {
     pub label: String,
     pub time: Time,
     pub data: Data,
+    // Panes from the top to the bottom. Channels that aren't in any of them are drawn on the data
+    // grid below them.
+    #[serde(default)]
+    pub panes: Vec<Pane>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Space,
+    Csv,
+    Tsv,
+    Binary,
+    Ndjson,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Space
+    }
+}
+
+/// A column of the input, either by its zero-based position or by its name in the header row. For
+/// NDJSON input, the name is the path to a field of the object, with nested fields separated by
+/// dots.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Default for Endianness {
+    fn default() -> Self {
+        Endianness::Little
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Crc {
+    Crc8,
+    Crc16,
+    Crc16Modbus,
+    Crc32,
+}
+
+/// Layout of a binary record: an optional sync word, the fields, and an optional CRC of the
+/// fields.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Layout {
+    #[serde(default)]
+    pub fields: Vec<FieldType>,
+    #[serde(default)]
+    pub endianness: Endianness,
+    pub sync: Option<Vec<u8>>,
+    pub crc: Option<Crc>,
+}
+
+/// What to do with a sample whose timestamp is the same as that of an already received one.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Duplicates {
+    KeepAll,
+    KeepLast,
+    Average,
+    Reject,
+}
+
+impl Default for Duplicates {
+    fn default() -> Self {
+        Duplicates::KeepLast
+    }
+}
+
+/// What to do with a sample that is older than the newest received one.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfOrder {
+    Accept,
+    Reject,
+}
+
+impl Default for OutOfOrder {
+    fn default() -> Self {
+        OutOfOrder::Accept
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Input {
+    pub source: Option<String>,
+    #[serde(default)]
+    pub format: Format,
+    #[serde(default)]
+    pub header: bool,
+    pub time_column: Option<Column>,
+    #[serde(default)]
+    pub layout: Layout,
+    // Whether every message from a message-oriented source like UDP starts with a sequence
+    // number.
+    #[serde(default)]
+    pub sequence_numbers: bool,
+    #[serde(default)]
+    pub duplicates: Duplicates,
+    #[serde(default)]
+    pub out_of_order: OutOfOrder,
+}
+
+/// Calibration of a channel, which turns raw values into physical ones before they are converted to
+/// grid units.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Transform {
+    /// The sum of every coefficient times the raw value to the power of its index.
+    Polynomial { coefficients: Vec<f64> },
+    /// Linear interpolation between `[raw, value]` points, ordered by the raw value.
+    Table { points: Vec<[f64; 2]> },
+    /// The Steinhart–Hart equation, which turns the resistance of a thermistor in ohms into its
+    /// temperature in kelvins.
+    SteinhartHart { a: f64, b: f64, c: f64 },
+}
+
+fn default_q() -> f64 {
+    30.0
+}
+
+/// A filter that smooths the values of a channel, or removes part of their spectrum. Frequencies
+/// are in Hz.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Filter {
+    /// The mean of the last `samples` values.
+    MovingAverage { samples: usize },
+    /// A first-order IIR low-pass filter.
+    LowPass { cutoff: f64 },
+    /// A first-order IIR high-pass filter.
+    HighPass { cutoff: f64 },
+    /// The median of the last `samples` values, which removes spikes without smearing edges.
+    Median { samples: usize },
+    /// A second-order notch filter, like for mains hum. A higher `q` makes the notch narrower.
+    Notch {
+        frequency: f64,
+        #[serde(default = "default_q")]
+        q: f64,
+    },
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Y {
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub column: Option<Column>,
+    // Formula computing a derived channel from other channels, instead of reading it from a column.
+    pub expression: Option<String>,
+    pub transform: Option<Transform>,
+    pub filter: Option<Filter>,
+    // Whether the unfiltered values are plotted along with the filtered ones.
+    #[serde(default)]
+    pub show_raw: bool,
     pub raw_offset: f32,
     pub raw_per_division: f32,
 }
 
+/// Limits on how much history is kept. The oldest samples are evicted once any of them is
+/// exceeded.
+#[derive(Deserialize, Debug, Default)]
+pub struct Retention {
+    pub max_samples: Option<usize>,
+    pub max_seconds: Option<f32>,
+    pub max_megabytes: Option<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerMode {
+    /// Runs freely when there are no edges, like when the signal is flat.
+    Auto,
+    /// Keeps the last triggered sweep until the next edge.
+    Normal,
+    /// Stops after the first edge until the trigger is armed again.
+    Single,
+}
+
+impl Default for TriggerMode {
+    fn default() -> Self {
+        TriggerMode::Auto
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Slope {
+    Rising,
+    Falling,
+    Either,
+}
+
+impl Default for Slope {
+    fn default() -> Self {
+        Slope::Rising
+    }
+}
+
+fn default_pre_trigger() -> f32 {
+    0.5
+}
+
+/// An edge trigger, which positions the plot on edges of a channel so that repetitive waveforms
+/// stand still.
+#[derive(Deserialize, Debug)]
+pub struct Trigger {
+    // Name of the source channel, or y1, y2 and so on.
+    pub channel: String,
+    #[serde(default)]
+    pub mode: TriggerMode,
+    // In the calibrated units of the channel.
+    #[serde(default)]
+    pub level: f32,
+    #[serde(default)]
+    pub slope: Slope,
+    // Seconds after an edge during which further edges are ignored.
+    #[serde(default)]
+    pub holdoff: f32,
+    // Part of the width of the plot shown before the edge, from 0 to 1.
+    #[serde(default = "default_pre_trigger")]
+    pub pre_trigger: f32,
+}
+
+/// Channels plotted against each other in XY mode, like for Lissajous figures or I-V curves.
+#[derive(Deserialize, Debug)]
+pub struct Xy {
+    // Names of the channels on the horizontal and the vertical axis, or y1, y2 and so on.
+    pub x: String,
+    pub y: String,
+}
+
+/// Window function applied to the samples before their spectrum is computed, trading the
+/// resolution of frequencies for the accuracy of amplitudes.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    FlatTop,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window::Hann
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpectrumScale {
+    Linear,
+    Db,
+}
+
+impl Default for SpectrumScale {
+    fn default() -> Self {
+        SpectrumScale::Db
+    }
+}
+
+fn default_averages() -> usize {
+    1
+}
+
+fn default_peaks() -> usize {
+    3
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Spectrum {
+    #[serde(default)]
+    pub window: Window,
+    #[serde(default)]
+    pub scale: SpectrumScale,
+    // Number of recent spectra that are averaged.
+    #[serde(default = "default_averages")]
+    pub averages: usize,
+    // Number of the highest peaks that are marked.
+    #[serde(default = "default_peaks")]
+    pub peaks: usize,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Spectrum {
+            window: Window::default(),
+            scale: SpectrumScale::default(),
+            averages: default_averages(),
+            peaks: default_peaks(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NamedColorMap {
+    Grayscale,
+    Viridis,
+    Inferno,
+    Jet,
+}
+
+/// Colors of levels from the lowest to the highest, either one of the built-in color maps or a list
+/// of RGB colors that are interpolated.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ColorMap {
+    Named(NamedColorMap),
+    Colors(Vec<[u8; 3]>),
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        ColorMap::Named(NamedColorMap::Inferno)
+    }
+}
+
+fn default_waterfall_points() -> usize {
+    256
+}
+
+fn default_columns() -> usize {
+    256
+}
+
+fn default_dynamic_range() -> f32 {
+    80.0
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Waterfall {
+    #[serde(default)]
+    pub color_map: ColorMap,
+    // Number of samples in the FFT of every column.
+    #[serde(default = "default_waterfall_points")]
+    pub points: usize,
+    // Number of columns across the width of the plot.
+    #[serde(default = "default_columns")]
+    pub columns: usize,
+    // Level in dB shown in the highest color. Defaults to the highest level in the plot.
+    pub max_db: Option<f32>,
+    // Range of levels in dB between the lowest and the highest color.
+    #[serde(default = "default_dynamic_range")]
+    pub dynamic_range: f32,
+}
+
+impl Default for Waterfall {
+    fn default() -> Self {
+        Waterfall {
+            color_map: ColorMap::default(),
+            points: default_waterfall_points(),
+            columns: default_columns(),
+            max_db: None,
+            dynamic_range: default_dynamic_range(),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct DataConfig {
+    #[serde(default)]
+    pub input: Input,
+    #[serde(default)]
+    pub retention: Retention,
+    pub trigger: Option<Trigger>,
+    pub xy: Option<Xy>,
+    #[serde(default)]
+    pub spectrum: Spectrum,
+    #[serde(default)]
+    pub waterfall: Waterfall,
     pub grid: Grid,
     pub y: Vec<Y>,
 }
 
+impl DataConfig {
+    /// Index of the channel with the given name, or of the channel referred to as y1, y2 and so on.
+    pub fn channel_index(&self, name: &str) -> Option<usize> {
+        self.y
+            .iter()
+            .position(|y| y.name.as_deref() == Some(name))
+            .or_else(|| {
+                name.strip_prefix('y')
+                    .and_then(|number| number.parse::<usize>().ok())
+                    .filter(|&number| number >= 1 && number <= self.y.len())
+                    .map(|number| number - 1)
+            })
+    }
+}
+
 pub struct Settings {
     pub data_config: String,
+    pub source: Option<String>,
+    pub input_format: Option<Format>,
+    pub error_log: Option<String>,
+    pub replay: Option<String>,
+    pub speed: f64,
     pub color_scheme: String,
     pub width: u32,
     pub height: u32,
@@ -72,6 +517,52 @@ pub fn parse_cli_options() -> Settings {
                 .required(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("source")
+                .long("source")
+                .value_name("SOURCE")
+                .help(
+                    "Sets the data source: stdin, file:PATH, udp:HOST:PORT, tcp:HOST:PORT or \
+                     tcp-listen:HOST:PORT.",
+                )
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("input-format")
+                .long("input-format")
+                .value_name("FORMAT")
+                .help("Overrides the input format set in the data config file.")
+                .required(false)
+                .takes_value(true)
+                .possible_values(&["space", "csv", "tsv", "binary", "ndjson"]),
+        )
+        .arg(
+            Arg::with_name("error-log")
+                .long("error-log")
+                .value_name("FILE")
+                .help("Logs unparsable lines to a file, or to stderr if set to \"stderr\".")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("FILE")
+                .help("Replays a recording according to its timestamps instead of plotting live.")
+                .required(false)
+                .takes_value(true)
+                .conflicts_with("source"),
+        )
+        .arg(
+            Arg::with_name("speed")
+                .long("speed")
+                .value_name("SPEED")
+                .help("Sets the replay speed, from 0.1 to 100 times real time.")
+                .required(false)
+                .takes_value(true)
+                .default_value("1"),
+        )
         .arg(
             Arg::with_name("color-scheme")
                 .long("color-scheme")
@@ -112,6 +603,17 @@ pub fn parse_cli_options() -> Settings {
 
     Settings {
         data_config: String::from(matches.value_of("data-config").unwrap()),
+        source: matches.value_of("source").map(String::from),
+        input_format: matches.value_of("input-format").map(|format| match format {
+            "csv" => Format::Csv,
+            "tsv" => Format::Tsv,
+            "binary" => Format::Binary,
+            "ndjson" => Format::Ndjson,
+            _ => Format::Space,
+        }),
+        error_log: matches.value_of("error-log").map(String::from),
+        replay: matches.value_of("replay").map(String::from),
+        speed: matches.value_of("speed").unwrap().parse::<f64>().unwrap(),
         color_scheme: String::from(matches.value_of("color-scheme").unwrap()),
         width: matches.value_of("width").unwrap().parse::<u32>().unwrap(),
         height: matches.value_of("height").unwrap().parse::<u32>().unwrap(),
@@ -121,10 +623,170 @@ pub fn parse_cli_options() -> Settings {
 
 pub fn read_data_config(data_config_filename: &str) -> DataConfig {
     let data_config = fs::read_to_string(data_config_filename).unwrap();
-    toml::from_str(&data_config).unwrap()
+    let data_config: DataConfig = toml::from_str(&data_config).unwrap();
+
+    assert!(
+        !data_config.y.is_empty(),
+        "The data config needs at least one [[y]] channel."
+    );
+
+    if let Timestamps::SampleRate = data_config.grid.time.timestamps {
+        assert!(
+            data_config.grid.time.sample_rate.is_some(),
+            "Timestamps derived from the sample rate require sample_rate to be set."
+        );
+    }
+
+    if let Some(bits) = data_config.grid.time.counter_bits {
+        assert!(
+            bits > 0 && bits <= 64,
+            "The counter width must be between 1 and 64 bits."
+        );
+    }
+
+    if data_config.grid.time.timestamps != Timestamps::Input {
+        assert!(
+            data_config.input.time_column.is_none(),
+            "A time column can't be set if timestamps aren't read from the input."
+        );
+    }
+
+    if let Some(trigger) = &data_config.trigger {
+        assert!(
+            data_config.channel_index(&trigger.channel).is_some(),
+            "The trigger channel \"{}\" doesn't exist.",
+            trigger.channel
+        );
+        assert!(
+            trigger.holdoff >= 0.0,
+            "The trigger holdoff can't be negative."
+        );
+        assert!(
+            trigger.pre_trigger >= 0.0 && trigger.pre_trigger <= 1.0,
+            "The pre-trigger part of the plot must be between 0 and 1."
+        );
+    }
+
+    for pane in &data_config.grid.panes {
+        assert!(pane.divisions > 0, "Every pane needs divisions.");
+        for channel in &pane.channels {
+            assert!(
+                data_config.channel_index(channel).is_some(),
+                "The pane channel \"{}\" doesn't exist.",
+                channel
+            );
+        }
+    }
+
+    if let Some(xy) = &data_config.xy {
+        for channel in [&xy.x, &xy.y].iter() {
+            assert!(
+                data_config.channel_index(channel).is_some(),
+                "The XY channel \"{}\" doesn't exist.",
+                channel
+            );
+        }
+    }
+
+    assert!(
+        data_config.spectrum.averages > 0,
+        "The spectrum needs at least one average."
+    );
+
+    let waterfall = &data_config.waterfall;
+    assert!(
+        waterfall.points >= 16 && waterfall.columns > 0,
+        "The waterfall needs at least 16 points and a column."
+    );
+    assert!(
+        waterfall.dynamic_range > 0.0,
+        "The dynamic range of the waterfall must be positive."
+    );
+    if let ColorMap::Colors(colors) = &waterfall.color_map {
+        assert!(
+            colors.len() >= 2,
+            "The color map of the waterfall needs at least two colors."
+        );
+    }
+
+    for (i, y) in data_config.y.iter().enumerate() {
+        assert!(
+            y.column.is_none() || y.expression.is_none(),
+            "Channel {} can't have both a column and an expression.",
+            i + 1
+        );
+
+        if let Some(name) = &y.name {
+            assert!(
+                data_config.y[..i]
+                    .iter()
+                    .all(|y| y.name.as_ref() != Some(name)),
+                "More than one channel is named \"{}\".",
+                name
+            );
+        }
+
+        match y.filter {
+            Some(Filter::MovingAverage { samples }) | Some(Filter::Median { samples }) => {
+                assert!(
+                    samples > 0,
+                    "The filter of channel {} needs samples.",
+                    i + 1
+                );
+            }
+            Some(Filter::LowPass { cutoff: frequency })
+            | Some(Filter::HighPass { cutoff: frequency }) => {
+                assert!(
+                    frequency > 0.0,
+                    "The filter frequency of channel {} must be positive.",
+                    i + 1
+                );
+            }
+            Some(Filter::Notch { frequency, q }) => {
+                assert!(
+                    frequency > 0.0 && q > 0.0,
+                    "The notch frequency and q of channel {} must be positive.",
+                    i + 1
+                );
+            }
+            None => {
+                assert!(
+                    !y.show_raw,
+                    "Channel {} has no filter, so there are no raw values to show.",
+                    i + 1
+                );
+            }
+        }
+
+        if let Some(Transform::Table { points }) = &y.transform {
+            assert!(
+                points.len() >= 2,
+                "The calibration table of channel {} needs at least two points.",
+                i + 1
+            );
+
+            let increasing = points.windows(2).all(|pair| pair[0][0] < pair[1][0]);
+            let decreasing = points.windows(2).all(|pair| pair[0][0] > pair[1][0]);
+            assert!(
+                increasing || decreasing,
+                "The raw values in the calibration table of channel {} must be strictly \
+                 increasing or decreasing.",
+                i + 1
+            );
+        }
+    }
+
+    data_config
 }
 
 pub fn read_color_scheme(color_scheme_filename: &str) -> ColorScheme {
     let color_scheme = fs::read_to_string(color_scheme_filename).unwrap();
-    toml::from_str(&color_scheme).unwrap()
+    let color_scheme: ColorScheme = toml::from_str(&color_scheme).unwrap();
+
+    assert!(
+        !color_scheme.channel.is_empty(),
+        "The color scheme needs at least one [[channel]] color."
+    );
+
+    color_scheme
 }
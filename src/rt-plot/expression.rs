@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Log10,
+    Sin,
+    Cos,
+    Tan,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "abs" => Some(Function::Abs),
+            "sqrt" => Some(Function::Sqrt),
+            "exp" => Some(Function::Exp),
+            "ln" => Some(Function::Ln),
+            "log10" => Some(Function::Log10),
+            "sin" => Some(Function::Sin),
+            "cos" => Some(Function::Cos),
+            "tan" => Some(Function::Tan),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Number(f64),
+    Channel(usize),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+/// A formula that computes the value of a derived channel from the values of other channels, like
+/// `sqrt(x^2 + y^2)`.
+#[derive(Debug)]
+pub struct Expression {
+    root: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                // Allow a sign right after the exponent, as in 1e-3.
+                let exponent_sign =
+                    (c == '-' || c == '+') && source[start..i].ends_with(|c| c == 'e' || c == 'E');
+                if !(c.is_ascii_alphanumeric() || c == '.' || exponent_sign) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            let number = &source[start..end];
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .map_err(|_| format!("invalid number \"{}\"", number))?,
+            ));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            tokens.push(Token::Identifier(String::from(&source[start..end])));
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// A recursive descent parser over the tokens of an expression.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    channels: &'a HashMap<String, usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            _ => Err(format!("expected '{}'", symbol)),
+        }
+    }
+
+    // sum = product (("+" | "-") product)*
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut expression = self.product()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol('+')) => Operator::Add,
+                Some(Token::Symbol('-')) => Operator::Subtract,
+                _ => return Ok(expression),
+            };
+            self.next();
+
+            expression = Node::Binary(operator, Box::new(expression), Box::new(self.product()?));
+        }
+    }
+
+    // product = unary (("*" | "/") unary)*
+    fn product(&mut self) -> Result<Node, String> {
+        let mut expression = self.unary()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol('*')) => Operator::Multiply,
+                Some(Token::Symbol('/')) => Operator::Divide,
+                _ => return Ok(expression),
+            };
+            self.next();
+
+            expression = Node::Binary(operator, Box::new(expression), Box::new(self.unary()?));
+        }
+    }
+
+    // unary = "-" unary | power
+    fn unary(&mut self) -> Result<Node, String> {
+        if let Some(Token::Symbol('-')) = self.peek() {
+            self.next();
+            return Ok(Node::Negate(Box::new(self.unary()?)));
+        }
+
+        self.power()
+    }
+
+    // power = atom ("^" unary)?, so that powers are right associative and -a^2 is -(a^2).
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+
+        if let Some(Token::Symbol('^')) = self.peek() {
+            self.next();
+            return Ok(Node::Binary(
+                Operator::Power,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+
+        Ok(base)
+    }
+
+    // atom = number | channel | function "(" sum ")" | "(" sum ")"
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Identifier(name)) => {
+                if let Some(Token::Symbol('(')) = self.peek() {
+                    let function = Function::from_name(&name)
+                        .ok_or_else(|| format!("unknown function \"{}\"", name))?;
+
+                    self.next();
+                    let argument = self.sum()?;
+                    self.expect(')')?;
+
+                    return Ok(Node::Call(function, Box::new(argument)));
+                }
+
+                match self.channels.get(&name) {
+                    Some(&channel) => Ok(Node::Channel(channel)),
+                    None => Err(format!("unknown channel \"{}\"", name)),
+                }
+            }
+            Some(Token::Symbol('(')) => {
+                let expression = self.sum()?;
+                self.expect(')')?;
+                Ok(expression)
+            }
+            _ => Err(String::from("expected a number, a channel or '('")),
+        }
+    }
+}
+
+impl Expression {
+    /// Parses an expression. Channels are referred to by the names in `channels`, which map them to
+    /// their indices.
+    pub fn parse(source: &str, channels: &HashMap<String, usize>) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            channels,
+        };
+
+        let root = parser.sum()?;
+
+        match parser.peek() {
+            Some(_) => Err(String::from(
+                "unexpected input after the end of the expression",
+            )),
+            None => Ok(Expression { root }),
+        }
+    }
+
+    /// Indices of the channels the expression refers to.
+    pub fn channels(&self) -> Vec<usize> {
+        let mut channels = vec![];
+        self.root.channels(&mut channels);
+        channels
+    }
+
+    pub fn evaluate(&self, values: &[f64]) -> f64 {
+        self.root.evaluate(values)
+    }
+}
+
+impl Node {
+    fn channels(&self, channels: &mut Vec<usize>) {
+        match self {
+            Node::Number(_) => {}
+            Node::Channel(channel) => channels.push(*channel),
+            Node::Negate(operand) | Node::Call(_, operand) => operand.channels(channels),
+            Node::Binary(_, left, right) => {
+                left.channels(channels);
+                right.channels(channels);
+            }
+        }
+    }
+
+    fn evaluate(&self, values: &[f64]) -> f64 {
+        match self {
+            Node::Number(number) => *number,
+            Node::Channel(channel) => values[*channel],
+            Node::Negate(operand) => -operand.evaluate(values),
+            Node::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                    Operator::Power => left.powf(right),
+                }
+            }
+            Node::Call(function, argument) => {
+                let argument = argument.evaluate(values);
+
+                match function {
+                    Function::Abs => argument.abs(),
+                    Function::Sqrt => argument.sqrt(),
+                    Function::Exp => argument.exp(),
+                    Function::Ln => argument.ln(),
+                    Function::Log10 => argument.log10(),
+                    Function::Sin => argument.sin(),
+                    Function::Cos => argument.cos(),
+                    Function::Tan => argument.tan(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, values: &[f64]) -> Result<f64, String> {
+        let channels: HashMap<String, usize> = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (String::from(*name), i))
+            .collect();
+
+        Expression::parse(source, &channels).map(|expression| expression.evaluate(values))
+    }
+
+    #[test]
+    fn multiplies_before_adding() {
+        assert_eq!(evaluate("a + b * c", &[1.0, 2.0, 3.0]), Ok(7.0));
+        assert_eq!(evaluate("(a + b) * c", &[1.0, 2.0, 3.0]), Ok(9.0));
+        assert_eq!(evaluate("a - b - c", &[1.0, 2.0, 3.0]), Ok(-4.0));
+        assert_eq!(evaluate("a / b / c", &[12.0, 2.0, 3.0]), Ok(2.0));
+    }
+
+    #[test]
+    fn raises_to_powers_from_the_right() {
+        assert_eq!(evaluate("a ^ b ^ c", &[2.0, 3.0, 2.0]), Ok(512.0));
+        assert_eq!(evaluate("-a^2", &[3.0]), Ok(-9.0));
+        assert_eq!(evaluate("(-a)^2", &[3.0]), Ok(9.0));
+        assert_eq!(evaluate("a^-1", &[4.0]), Ok(0.25));
+    }
+
+    #[test]
+    fn reads_exponents_before_operators() {
+        assert_eq!(evaluate("1e-3-a", &[1.0]), Ok(1e-3 - 1.0));
+        assert_eq!(evaluate("2E+2-1", &[]), Ok(199.0));
+        assert_eq!(evaluate("a-1e-3", &[1.0]), Ok(1.0 - 1e-3));
+        assert_eq!(evaluate(".5*sqrt(a)", &[16.0]), Ok(2.0));
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert_eq!(
+            evaluate("a + d", &[]),
+            Err(String::from("unknown channel \"d\""))
+        );
+        assert_eq!(
+            evaluate("log(a)", &[]),
+            Err(String::from("unknown function \"log\""))
+        );
+        assert_eq!(
+            evaluate("1.2.3", &[]),
+            Err(String::from("invalid number \"1.2.3\""))
+        );
+        assert_eq!(
+            evaluate("a % b", &[]),
+            Err(String::from("unexpected character '%'"))
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_input() {
+        assert_eq!(
+            evaluate("a b", &[]),
+            Err(String::from(
+                "unexpected input after the end of the expression"
+            ))
+        );
+        assert_eq!(
+            evaluate("(a + b))", &[]),
+            Err(String::from(
+                "unexpected input after the end of the expression"
+            ))
+        );
+        assert_eq!(evaluate("sqrt(a", &[]), Err(String::from("expected ')'")));
+        assert_eq!(
+            evaluate("a +", &[]),
+            Err(String::from("expected a number, a channel or '('"))
+        );
+    }
+}
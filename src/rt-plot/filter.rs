@@ -0,0 +1,192 @@
+use super::config::Filter;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// The state of a filter running over the values of a channel.
+pub struct FilterState {
+    filter: Filter,
+    // The last values, for filters over a window of samples.
+    window: VecDeque<f64>,
+    last_time: Option<f64>,
+    // Smoothed time between samples in seconds, for filters that assume a fixed sample rate.
+    interval: Option<f64>,
+    // The last inputs and outputs, most recent first.
+    inputs: [f64; 2],
+    outputs: [f64; 2],
+}
+
+impl FilterState {
+    pub fn new(filter: &Filter) -> FilterState {
+        FilterState {
+            filter: filter.clone(),
+            window: VecDeque::new(),
+            last_time: None,
+            interval: None,
+            inputs: [0.0; 2],
+            outputs: [0.0; 2],
+        }
+    }
+
+    /// Filters the value of a sample taken at `time` seconds. Gaps pass through without affecting
+    /// the state.
+    pub fn apply(&mut self, time: f64, value: f64) -> f64 {
+        if value.is_nan() {
+            return value;
+        }
+
+        let dt = self.last_time.map(|last_time| time - last_time);
+        self.last_time = Some(time);
+
+        // Samples with the same or an older timestamp are assumed to be a regular interval apart.
+        let dt = match dt {
+            Some(dt) if dt > 0.0 => {
+                self.interval = Some(match self.interval {
+                    Some(interval) => interval + (dt - interval) * 0.01,
+                    None => dt,
+                });
+                Some(dt)
+            }
+            Some(_) => self.interval,
+            None => None,
+        };
+
+        let output = match (&self.filter, dt) {
+            (Filter::MovingAverage { samples }, _) => {
+                self.push(value, *samples);
+                self.window.iter().sum::<f64>() / self.window.len() as f64
+            }
+            (Filter::Median { samples }, _) => {
+                self.push(value, *samples);
+
+                let mut sorted: Vec<f64> = self.window.iter().cloned().collect();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                match sorted.len() % 2 {
+                    0 => (sorted[sorted.len() / 2 - 1] + sorted[sorted.len() / 2]) / 2.0,
+                    _ => sorted[sorted.len() / 2],
+                }
+            }
+            (Filter::LowPass { cutoff }, Some(dt)) => {
+                let rc = 1.0 / (2.0 * PI * cutoff);
+                self.outputs[0] + dt / (rc + dt) * (value - self.outputs[0])
+            }
+            (Filter::HighPass { cutoff }, Some(dt)) => {
+                let rc = 1.0 / (2.0 * PI * cutoff);
+                rc / (rc + dt) * (self.outputs[0] + value - self.inputs[0])
+            }
+            (Filter::Notch { frequency, q }, Some(_)) => {
+                // A biquad notch, as in the Audio EQ Cookbook.
+                let omega = 2.0 * PI * frequency * self.interval.unwrap();
+                let alpha = omega.sin() / (2.0 * q);
+                let cos = omega.cos();
+
+                (value - 2.0 * cos * self.inputs[0] + self.inputs[1] + 2.0 * cos * self.outputs[0]
+                    - (1.0 - alpha) * self.outputs[1])
+                    / (1.0 + alpha)
+            }
+            // The first value has no frequency content besides its constant part.
+            (Filter::HighPass { .. }, None) => 0.0,
+            (_, None) => value,
+        };
+
+        match dt {
+            Some(_) => {
+                self.inputs = [value, self.inputs[0]];
+                self.outputs = [output, self.outputs[0]];
+            }
+            // Start from a steady state, as if the first value had always been there.
+            None => {
+                self.inputs = [value; 2];
+                self.outputs = [output; 2];
+            }
+        }
+
+        output
+    }
+
+    fn push(&mut self, value: f64, samples: usize) {
+        self.window.push_back(value);
+        if self.window.len() > samples {
+            self.window.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 1000.0;
+
+    fn apply(filter: Filter, values: &[f64]) -> Vec<f64> {
+        let mut state = FilterState::new(&filter);
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| state.apply(i as f64 / SAMPLE_RATE, value))
+            .collect()
+    }
+
+    // The amplitude of the output of a sine of the given frequency once the filter has settled,
+    // from its RMS over the last second, which is a whole number of periods.
+    fn amplitude(filter: Filter, frequency: f64) -> f64 {
+        let values: Vec<f64> = (0..5000)
+            .map(|i| (2.0 * PI * frequency * i as f64 / SAMPLE_RATE).sin())
+            .collect();
+
+        let output = &apply(filter, &values)[4000..];
+        let mean_square = output.iter().map(|value| value * value).sum::<f64>() / 1000.0;
+        (2.0 * mean_square).sqrt()
+    }
+
+    #[test]
+    fn averages_the_last_samples() {
+        let filter = Filter::MovingAverage { samples: 3 };
+        let output = apply(filter, &[1.0, 2.0, 3.0, f64::NAN, 4.0]);
+
+        assert_eq!(output[..3], [1.0, 1.5, 2.0]);
+        assert!(output[3].is_nan());
+        assert_eq!(output[4], 3.0);
+    }
+
+    #[test]
+    fn removes_spikes_with_the_median() {
+        let filter = Filter::Median { samples: 3 };
+
+        assert_eq!(
+            apply(filter.clone(), &[0.0, 0.0, 100.0, 0.0, 1.0, 1.0]),
+            vec![0.0, 0.0, 0.0, 0.0, 1.0, 1.0]
+        );
+        assert_eq!(apply(filter, &[1.0, 4.0]), vec![1.0, 2.5]);
+    }
+
+    #[test]
+    fn passes_low_frequencies_through_the_low_pass() {
+        let filter = Filter::LowPass { cutoff: 10.0 };
+
+        assert!((apply(filter.clone(), &[2.0; 1000])[999] - 2.0).abs() < 1e-9);
+        assert!(amplitude(filter.clone(), 1.0) > 0.99);
+        assert!(amplitude(filter, 400.0) < 0.05);
+    }
+
+    #[test]
+    fn passes_high_frequencies_through_the_high_pass() {
+        let filter = Filter::HighPass { cutoff: 10.0 };
+
+        assert!(apply(filter.clone(), &[2.0; 1000])[999].abs() < 1e-9);
+        // A first-order filter at this sample rate lets through a little less than all of it.
+        assert!(amplitude(filter.clone(), 200.0) > 0.95);
+        assert!(amplitude(filter, 1.0) < 0.15);
+    }
+
+    #[test]
+    fn removes_the_notch_frequency() {
+        let filter = Filter::Notch {
+            frequency: 50.0,
+            q: 30.0,
+        };
+
+        assert!(amplitude(filter.clone(), 50.0) < 0.01);
+        assert!(amplitude(filter.clone(), 100.0) > 0.99);
+        assert!(amplitude(filter, 10.0) > 0.99);
+    }
+}
@@ -0,0 +1,381 @@
+use super::clock::Clock;
+use super::config::{DataConfig, Y};
+use super::expression::Expression;
+use super::filter::FilterState;
+use super::input::{ParseError, Parser};
+use super::source::{Chunk, DataSource};
+use super::status::Status;
+use ordered_float::OrderedFloat;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A timestamp and the values of every channel, followed by the unfiltered values of the channels
+/// that show them, all in grid units.
+pub type Sample = (OrderedFloat<f32>, Vec<f32>);
+
+// How often the stop signal is checked while waiting for input.
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn calibrate(y: &Y, value: f64) -> f64 {
+    match &y.transform {
+        Some(transform) => transform.apply(value),
+        None => value,
+    }
+}
+
+// Compiles the expressions of derived channels. Channels are referred to by their name, or as y1,
+// y2 and so on. A derived channel can only use input channels and derived channels before it, since
+// they're computed in order.
+fn compile_expressions(data_config: &DataConfig) -> Vec<(usize, Expression)> {
+    let mut channels = HashMap::new();
+    for (i, y) in data_config.y.iter().enumerate() {
+        channels.insert(format!("y{}", i + 1), i);
+        if let Some(name) = &y.name {
+            channels.insert(name.clone(), i);
+        }
+    }
+
+    let mut derived = vec![];
+    for (i, y) in data_config.y.iter().enumerate() {
+        if let Some(source) = &y.expression {
+            let expression = Expression::parse(source, &channels).unwrap_or_else(|error| {
+                panic!("Invalid expression of channel {}: {}.", i + 1, error)
+            });
+
+            for channel in expression.channels() {
+                assert!(
+                    data_config.y[channel].expression.is_none() || channel < i,
+                    "The expression of channel {} uses channel {}, which is derived after it.",
+                    i + 1,
+                    channel + 1
+                );
+            }
+
+            derived.push((i, expression));
+        }
+    }
+
+    derived
+}
+
+/// Turns lines or records of input into samples in grid units.
+pub struct Pipeline {
+    data_config: Arc<DataConfig>,
+    parser: Parser,
+    derived: Vec<(usize, Expression)>,
+    filters: Vec<Option<FilterState>>,
+    clock: Clock,
+    status: Arc<Status>,
+    error_log: Option<Box<dyn Write + Send>>,
+}
+
+impl Pipeline {
+    pub fn new(
+        data_config: Arc<DataConfig>,
+        status: Arc<Status>,
+        error_log: Option<Box<dyn Write + Send>>,
+    ) -> Pipeline {
+        Pipeline {
+            parser: Parser::new(&data_config),
+            derived: compile_expressions(&data_config),
+            filters: data_config
+                .y
+                .iter()
+                .map(|y| y.filter.as_ref().map(FilterState::new))
+                .collect(),
+            clock: Clock::new(&data_config.grid.time, status.clone()),
+            data_config,
+            status,
+            error_log,
+        }
+    }
+
+    /// Why the input can't be processed any further, if it can't.
+    pub fn failure(&self) -> Option<&str> {
+        self.parser.failure()
+    }
+
+    /// Starts over after the connection to the source was lost, since a new one starts with the
+    /// header again.
+    pub fn reset(&mut self) {
+        self.parser.reset();
+    }
+
+    /// Processes a single line or record.
+    pub fn process(&mut self, record: &[u8]) -> Option<Sample> {
+        let parsed = self.parser.parse(record);
+        self.transform(parsed, record)
+    }
+
+    /// Processes whatever remains at the end of a message or of the input.
+    pub fn process_remainder(&mut self, remainder: &[u8]) -> Option<Sample> {
+        let parsed = self.parser.parse_remainder(remainder);
+        self.transform(parsed, remainder)
+    }
+
+    /// Splits off as many complete records from the start of `bytes` as possible and processes
+    /// them, returning the number of bytes consumed.
+    pub fn process_records(&mut self, bytes: &[u8], samples: &mut Vec<Sample>) -> usize {
+        let mut start = 0;
+
+        while let Some((record, length)) = self.parser.next_record(&bytes[start..]) {
+            match record {
+                Ok(record) => {
+                    let record = start + record.start..start + record.end;
+                    samples.extend(self.process(&bytes[record]));
+                }
+                Err(error) => {
+                    let skipped = &bytes[start..start + length];
+                    self.transform(Err(error), skipped);
+                }
+            }
+
+            start += length;
+        }
+
+        start
+    }
+
+    fn transform(
+        &mut self,
+        parsed: Result<Option<(Option<f64>, Vec<f64>)>, ParseError>,
+        record: &[u8],
+    ) -> Option<Sample> {
+        // Malformed lines are skipped and counted, since serial streams in particular tend to
+        // start with garbage.
+        let (time, values) = match parsed {
+            Ok(Some(parts)) => parts,
+            Ok(None) => return None,
+            Err(error) => {
+                self.status.record_parse_error(error);
+
+                if let Some(error_log) = &mut self.error_log {
+                    writeln!(error_log, "{}: {}", error, String::from_utf8_lossy(record)).ok();
+                }
+
+                return None;
+            }
+        };
+
+        let data_config = &self.data_config;
+
+        let seconds = self.clock.timestamp(time) / data_config.grid.time.raw_per_second as f64;
+
+        // Transform time to grid units.
+        let time = (seconds / data_config.grid.time.seconds_per_division as f64) as f32;
+
+        // Calibrate the input channels, then compute the derived ones from them. Derived channels
+        // use the filtered values.
+        let mut inputs = values.into_iter();
+        let mut raw = vec![f64::NAN; data_config.y.len()];
+        let mut values = vec![f64::NAN; data_config.y.len()];
+
+        for (i, y) in data_config.y.iter().enumerate() {
+            if y.expression.is_none() {
+                raw[i] = calibrate(y, inputs.next().unwrap());
+                values[i] = match &mut self.filters[i] {
+                    Some(filter) => filter.apply(seconds, raw[i]),
+                    None => raw[i],
+                };
+            }
+        }
+
+        for (i, expression) in &self.derived {
+            raw[*i] = calibrate(&data_config.y[*i], expression.evaluate(&values));
+            values[*i] = match &mut self.filters[*i] {
+                Some(filter) => filter.apply(seconds, raw[*i]),
+                None => raw[*i],
+            };
+        }
+
+        // The unfiltered values of the channels that show them come after all the channels.
+        let traces = (0..data_config.y.len()).map(|i| (i, values[i])).chain(
+            (0..data_config.y.len())
+                .filter(|&i| data_config.y[i].show_raw)
+                .map(|i| (i, raw[i])),
+        );
+
+        // Transform data to grid units.
+        let y: Vec<f32> = traces
+            .map(|(i, y)| {
+                ((y - data_config.y[i].raw_offset as f64)
+                    / data_config.y[i].raw_per_division as f64) as f32
+            })
+            .collect();
+
+        Some((OrderedFloat(time), y))
+    }
+}
+
+/// Delivers the samples parsed from a data source. Sources only produce bytes, so that framing and
+/// parsing are shared and every source supports every format.
+pub struct SampleSource {
+    source: Box<dyn DataSource>,
+    pipeline: Pipeline,
+    // Bytes of a record that hasn't been completely received yet.
+    pending: Vec<u8>,
+}
+
+impl SampleSource {
+    pub fn new(source: Box<dyn DataSource>, pipeline: Pipeline) -> SampleSource {
+        SampleSource {
+            source,
+            pipeline,
+            pending: vec![],
+        }
+    }
+
+    /// Waits at most `timeout` for more input and returns the samples parsed from it, or `None`
+    /// once the input has ended.
+    pub fn read(&mut self, timeout: Duration) -> io::Result<Option<Vec<Sample>>> {
+        let (bytes, complete) = match self.source.read(timeout)? {
+            Chunk::Stream(bytes) => (bytes, false),
+            Chunk::Message(bytes) => (bytes, true),
+            Chunk::Idle => return Ok(Some(vec![])),
+            Chunk::Disconnected => {
+                self.pending.clear();
+                self.pipeline.reset();
+                return Ok(Some(vec![]));
+            }
+            Chunk::End => return Ok(None),
+        };
+
+        self.pending.extend_from_slice(&bytes);
+
+        let mut samples = vec![];
+        let consumed = self.pipeline.process_records(&self.pending, &mut samples);
+        self.pending.drain(..consumed);
+
+        // Nothing of a message carries over to the next one.
+        if complete && !self.pending.is_empty() {
+            samples.extend(self.pipeline.process_remainder(&self.pending));
+            self.pending.clear();
+        }
+
+        match self.pipeline.failure() {
+            Some(failure) => Err(io::Error::new(io::ErrorKind::InvalidData, failure)),
+            None => Ok(Some(samples)),
+        }
+    }
+
+    /// Processes whatever is left of the input once reading stops.
+    pub fn finish(mut self) -> Vec<Sample> {
+        match self.pending.is_empty() {
+            true => vec![],
+            false => self
+                .pipeline
+                .process_remainder(&self.pending)
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// Reads from the source until it ends or the stop signal is set, and sends the resulting samples
+/// to the render loop in chunks. Sending never blocks, so reading never waits on rendering.
+pub fn run(
+    source: Box<dyn DataSource>,
+    pipeline: Pipeline,
+    sender: Sender<Vec<Sample>>,
+    stop_signal: Arc<AtomicBool>,
+) {
+    let status = pipeline.status.clone();
+    let mut source = SampleSource::new(source, pipeline);
+
+    while !stop_signal.load(Ordering::SeqCst) {
+        match source.read(POLL_TIMEOUT) {
+            Ok(Some(samples)) => {
+                // The render loop has stopped.
+                if !samples.is_empty() && sender.send(samples).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => {
+                status.set_source_state(Some(String::from("Input ended")));
+                break;
+            }
+            Err(error) => {
+                status.set_source_state(Some(format!("Input error: {}", error)));
+                break;
+            }
+        }
+    }
+
+    let samples = source.finish();
+    if !samples.is_empty() {
+        sender.send(samples).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Hands out the chunks it was given, and then ends.
+    struct ScriptedSource(VecDeque<Chunk>);
+
+    impl DataSource for ScriptedSource {
+        fn read(&mut self, _: Duration) -> io::Result<Chunk> {
+            Ok(self.0.pop_front().unwrap_or(Chunk::End))
+        }
+    }
+
+    #[test]
+    fn expects_header_after_reconnect() {
+        let data_config: DataConfig = toml::from_str(
+            r#"
+            [input]
+            format = "csv"
+            header = true
+            time_column = "t"
+
+            [grid]
+            label = ""
+            [grid.time]
+            divisions = 10
+            seconds_per_division = 1.0
+            raw_per_second = 1.0
+            label = ""
+            [grid.data]
+            divisions = 4
+            zero_shift = 0.0
+            label = ""
+
+            [[y]]
+            column = "a"
+            raw_offset = 0.0
+            raw_per_division = 1.0
+            "#,
+        )
+        .unwrap();
+
+        let status = Arc::new(Status::new());
+        let pipeline = Pipeline::new(Arc::new(data_config), status.clone(), None);
+        let chunks = vec![
+            Chunk::Stream(b"t,a\n1,2\n".to_vec()),
+            Chunk::Disconnected,
+            Chunk::Stream(b"t,a\n3,4\n".to_vec()),
+        ];
+        let mut source = SampleSource::new(Box::new(ScriptedSource(chunks.into())), pipeline);
+
+        let mut samples = vec![];
+        while let Some(chunk) = source.read(POLL_TIMEOUT).unwrap() {
+            samples.extend(chunk);
+        }
+
+        assert_eq!(
+            samples,
+            vec![
+                (OrderedFloat(1.0), vec![2.0]),
+                (OrderedFloat(3.0), vec![4.0])
+            ]
+        );
+        assert_eq!(status.summary(), None);
+    }
+}
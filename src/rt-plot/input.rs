@@ -0,0 +1,443 @@
+use super::binary::Decoder;
+use super::config::{Column, DataConfig, Format, Layout, Timestamps};
+use serde_json::Value;
+use std::fmt;
+use std::ops::Range;
+
+/// Reasons a line of input can be rejected. Malformed lines are skipped and counted instead of
+/// stopping the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseError {
+    Empty,
+    Encoding,
+    MissingColumn,
+    ExtraColumn,
+    InvalidNumber,
+    Truncated,
+    Sync,
+    Crc,
+    InvalidJson,
+}
+
+impl ParseError {
+    pub const ALL: [ParseError; 9] = [
+        ParseError::Empty,
+        ParseError::Encoding,
+        ParseError::MissingColumn,
+        ParseError::ExtraColumn,
+        ParseError::InvalidNumber,
+        ParseError::Truncated,
+        ParseError::Sync,
+        ParseError::Crc,
+        ParseError::InvalidJson,
+    ];
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            ParseError::Empty => "empty",
+            ParseError::Encoding => "invalid encoding",
+            ParseError::MissingColumn => "missing column",
+            ParseError::ExtraColumn => "extra column",
+            ParseError::InvalidNumber => "invalid number",
+            ParseError::Truncated => "truncated",
+            ParseError::Sync => "out of sync",
+            ParseError::Crc => "CRC mismatch",
+            ParseError::InvalidJson => "invalid JSON",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+pub struct Parser {
+    format: Format,
+    // Column names are only known after the header row has been read.
+    has_header: bool,
+    header: Option<Vec<String>>,
+    expecting_header: bool,
+    // Set if the header doesn't match the data config, after which nothing more is parsed.
+    failure: Option<String>,
+    // There is no time column if timestamps aren't read from the input.
+    time_column: Option<Column>,
+    channel_columns: Vec<Option<Column>>,
+    // Set if no column is selected explicitly, in which case lines must have exactly the columns
+    // that are read, so that two lines run together aren't taken for one.
+    positional: bool,
+    // Resolved column indices, computed once the header (if any) has been read.
+    time_index: Option<usize>,
+    channel_indices: Vec<usize>,
+    layout: Layout,
+    decoder: Option<Decoder>,
+    // JSON pointers to the fields of NDJSON objects.
+    json_time: Option<String>,
+    json_channels: Vec<String>,
+}
+
+// Turns a column name into a JSON pointer. Nested fields are separated by dots, unless the name is
+// already a JSON pointer.
+fn json_pointer(column: &Column) -> String {
+    match column {
+        Column::Name(name) if name.starts_with('/') => name.clone(),
+        Column::Name(name) => format!("/{}", name.replace('.', "/")),
+        Column::Index(_) => panic!("Fields of JSON objects can only be selected by name."),
+    }
+}
+
+impl Parser {
+    pub fn new(data_config: &DataConfig) -> Parser {
+        let mut parser = Parser {
+            format: data_config.input.format,
+            has_header: data_config.input.header,
+            header: None,
+            expecting_header: data_config.input.header,
+            failure: None,
+            time_column: match data_config.grid.time.timestamps {
+                Timestamps::Input => Some(
+                    data_config
+                        .input
+                        .time_column
+                        .clone()
+                        .unwrap_or(Column::Index(0)),
+                ),
+                _ => None,
+            },
+            // Derived channels aren't read from the input.
+            channel_columns: data_config
+                .y
+                .iter()
+                .filter(|y| y.expression.is_none())
+                .map(|y| y.column.clone())
+                .collect(),
+            positional: data_config.input.time_column.is_none()
+                && data_config.y.iter().all(|y| y.column.is_none()),
+            time_index: None,
+            channel_indices: vec![],
+            layout: data_config.input.layout.clone(),
+            decoder: None,
+            json_time: None,
+            json_channels: vec![],
+        };
+
+        if let Format::Ndjson = parser.format {
+            assert!(
+                !parser.expecting_header,
+                "NDJSON input can't have a header."
+            );
+
+            parser.json_time = match data_config.grid.time.timestamps {
+                Timestamps::Input => Some(json_pointer(
+                    data_config
+                        .input
+                        .time_column
+                        .as_ref()
+                        .expect("NDJSON input requires the time_column to be set."),
+                )),
+                _ => None,
+            };
+            parser.json_channels = data_config
+                .y
+                .iter()
+                .filter(|y| y.expression.is_none())
+                .map(|y| {
+                    json_pointer(
+                        y.column
+                            .as_ref()
+                            .expect("NDJSON channels require a column."),
+                    )
+                })
+                .collect();
+
+            return parser;
+        }
+
+        assert!(
+            !(parser.expecting_header && parser.format == Format::Binary),
+            "Binary input can't have a header."
+        );
+
+        if !parser.expecting_header {
+            parser
+                .resolve_columns()
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+
+        parser
+    }
+
+    // Splits a line into its fields, without the quotes around them, like in spreadsheet exports.
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        let fields: Vec<&str> = match self.format {
+            Format::Space => line.split_whitespace().collect(),
+            Format::Csv => line.split(',').map(|field| field.trim()).collect(),
+            Format::Tsv => line.split('\t').map(|field| field.trim()).collect(),
+            Format::Binary | Format::Ndjson => unreachable!(),
+        };
+
+        fields
+            .into_iter()
+            .map(|field| field.trim_matches('"'))
+            .collect()
+    }
+
+    /// Finds the next complete record at the start of `bytes`. Returns the range of the record, or
+    /// the reason it was rejected, along with the number of bytes it takes up including any
+    /// delimiter. Returns `None` if more bytes are needed.
+    pub fn next_record(&self, bytes: &[u8]) -> Option<(Result<Range<usize>, ParseError>, usize)> {
+        match &self.decoder {
+            Some(decoder) => decoder.next_frame(bytes),
+            None => bytes
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map(|length| (Ok(0..length), length + 1)),
+        }
+    }
+
+    /// Handles whatever is left at the end of a message or of the input, which is a complete line
+    /// for text formats, but an incomplete record for binary ones.
+    pub fn parse_remainder(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<Option<(Option<f64>, Vec<f64>)>, ParseError> {
+        match self.format {
+            Format::Binary => Err(ParseError::Truncated),
+            _ => self.parse(bytes),
+        }
+    }
+
+    fn parse_binary(&self, record: &[u8]) -> Result<Option<(Option<f64>, Vec<f64>)>, ParseError> {
+        let fields = self.decoder.as_ref().unwrap().decode(record);
+
+        let time = self.time_index.map(|index| fields[index]);
+        let values = self.channel_indices.iter().map(|&i| fields[i]).collect();
+
+        Ok(Some((time, values)))
+    }
+
+    fn parse_json(&self, line: &[u8]) -> Result<Option<(Option<f64>, Vec<f64>)>, ParseError> {
+        if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+            return Err(ParseError::Empty);
+        }
+
+        let object: Value = serde_json::from_slice(line).map_err(|_| ParseError::InvalidJson)?;
+
+        let time = match &self.json_time {
+            Some(pointer) => match object.pointer(pointer) {
+                Some(time) => Some(time.as_f64().ok_or(ParseError::InvalidNumber)?),
+                None => return Err(ParseError::MissingColumn),
+            },
+            None => None,
+        };
+
+        // Missing fields leave a gap in the channel instead of rejecting the whole line.
+        let values = self
+            .json_channels
+            .iter()
+            .map(|pointer| match object.pointer(pointer) {
+                None | Some(Value::Null) => Ok(f64::NAN),
+                Some(value) => value.as_f64().ok_or(ParseError::InvalidNumber),
+            })
+            .collect::<Result<Vec<f64>, ParseError>>()?;
+
+        Ok(Some((time, values)))
+    }
+
+    fn resolve_column(&self, column: &Column) -> Result<usize, String> {
+        match column {
+            Column::Index(index) => Ok(*index),
+            Column::Name(name) => {
+                let header = self.header.as_ref().ok_or_else(|| {
+                    String::from("Columns can only be selected by name if the input has a header.")
+                })?;
+
+                header
+                    .iter()
+                    .position(|column_name| column_name == name)
+                    .ok_or_else(|| format!("Column \"{}\" not found in the header.", name))
+            }
+        }
+    }
+
+    fn resolve_columns(&mut self) -> Result<(), String> {
+        self.time_index = match &self.time_column {
+            Some(column) => Some(self.resolve_column(column)?),
+            None => None,
+        };
+
+        // Channels without an explicit column take the remaining columns in order, skipping the
+        // time column.
+        let time_index = self.time_index;
+        let mut positional = (0..).filter(|&i| Some(i) != time_index);
+
+        self.channel_indices = self
+            .channel_columns
+            .iter()
+            .map(|column| match column {
+                Some(column) => self.resolve_column(column),
+                None => Ok(positional.next().unwrap()),
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        if let Format::Binary = self.format {
+            let columns = self
+                .channel_indices
+                .iter()
+                .chain(self.time_index.iter())
+                .max()
+                .map_or(0, |&index| index + 1);
+
+            self.decoder = Some(Decoder::new(&self.layout, columns));
+        }
+
+        Ok(())
+    }
+
+    /// Expects the header row again, like at the start of a new connection.
+    pub fn reset(&mut self) {
+        self.expecting_header = self.has_header;
+    }
+
+    /// Why the input can't be parsed any further, like a header without the columns of the data
+    /// config.
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    /// Parses a single line or binary record into a timestamp, if the input contains one, and the
+    /// values of every channel, both in raw units. Returns `None` for the header row.
+    pub fn parse(&mut self, line: &[u8]) -> Result<Option<(Option<f64>, Vec<f64>)>, ParseError> {
+        if self.failure.is_some() {
+            return Ok(None);
+        }
+
+        match self.format {
+            Format::Binary => return self.parse_binary(line),
+            Format::Ndjson => return self.parse_json(line),
+            _ => {}
+        }
+
+        let line = std::str::from_utf8(line).map_err(|_| ParseError::Encoding)?;
+        let fields = self.split(line.trim());
+
+        if fields.is_empty() || fields == [""] {
+            return Err(ParseError::Empty);
+        }
+
+        if self.expecting_header {
+            self.header = Some(fields.iter().map(|field| field.to_string()).collect());
+            self.expecting_header = false;
+            self.failure = self.resolve_columns().err();
+
+            return Ok(None);
+        }
+
+        // Parse everything as a 64-bit float so that signed integers, decimals and scientific
+        // notation are all accepted, and so that large integer timestamps keep their precision.
+        let parse = |index: usize| match fields.get(index) {
+            Some(field) => field.parse::<f64>().map_err(|_| ParseError::InvalidNumber),
+            None => Err(ParseError::MissingColumn),
+        };
+
+        let columns = self.channel_indices.len() + self.time_index.iter().count();
+        if self.positional && fields.len() > columns {
+            return Err(ParseError::ExtraColumn);
+        }
+
+        let time = match self.time_index {
+            Some(index) => Some(parse(index)?),
+            None => None,
+        };
+        let values = self
+            .channel_indices
+            .iter()
+            .map(|&i| parse(i))
+            .collect::<Result<Vec<f64>, ParseError>>()?;
+
+        Ok(Some((time, values)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(input: &str, channels: &str) -> Parser {
+        let data_config: DataConfig = toml::from_str(&format!(
+            r#"
+            [input]
+            {}
+
+            [grid]
+            label = ""
+            [grid.time]
+            divisions = 10
+            seconds_per_division = 1.0
+            raw_per_second = 1.0
+            label = ""
+            [grid.data]
+            divisions = 4
+            zero_shift = 0.0
+            label = ""
+
+            {}
+            "#,
+            input, channels
+        ))
+        .unwrap();
+
+        Parser::new(&data_config)
+    }
+
+    const CHANNELS: &str = r#"
+        [[y]]
+        raw_offset = 0.0
+        raw_per_division = 1.0
+        [[y]]
+        raw_offset = 0.0
+        raw_per_division = 1.0
+    "#;
+
+    #[test]
+    fn rejects_extra_columns_of_positional_input() {
+        let mut parser = parser("", CHANNELS);
+
+        assert_eq!(
+            parser.parse(b"432 121 65").unwrap(),
+            Some((Some(432.0), vec![121.0, 65.0]))
+        );
+        assert_eq!(
+            parser.parse(b"432 121 65432 121 65"),
+            Err(ParseError::ExtraColumn)
+        );
+    }
+
+    #[test]
+    fn ignores_columns_that_are_not_selected() {
+        let mut parser = parser(
+            "",
+            r#"
+            [[y]]
+            column = 3
+            raw_offset = 0.0
+            raw_per_division = 1.0
+            "#,
+        );
+
+        assert_eq!(
+            parser.parse(b"1 2 3 4 5").unwrap(),
+            Some((Some(1.0), vec![4.0]))
+        );
+    }
+
+    #[test]
+    fn unquotes_fields() {
+        let mut parser = parser("format = \"csv\"\nheader = true", CHANNELS);
+
+        assert_eq!(parser.parse(b"\"t\",\"a\",\"b\"").unwrap(), None);
+        assert_eq!(
+            parser.parse(b"\"1\",\"2.5\",\"-3\"").unwrap(),
+            Some((Some(1.0), vec![2.5, -3.0]))
+        );
+    }
+}
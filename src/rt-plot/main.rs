@@ -1,22 +1,44 @@
 mod approximation;
+mod autoset;
+mod binary;
+mod calibration;
+mod clock;
 mod config;
+mod expression;
+mod filter;
+mod ingest;
+mod input;
+mod playback;
 mod plot;
 mod renderer;
+mod source;
+mod spectrum;
+mod status;
+mod store;
 mod text;
+mod trigger;
+mod waterfall;
 
-use config::{ColorScheme, DataConfig};
+use autoset::Scales;
+use config::{ColorScheme, DataConfig, Retention, Slope, TriggerMode};
 use glfw::{Action, Context, Key};
 use nalgebra::{Matrix3, Vector2};
 use ordered_float::OrderedFloat;
+use playback::Playback;
 use rusttype::Font;
+use spectrum::Spectrum;
+use status::Status;
 use std::cmp;
-use std::collections::BTreeMap;
 use std::io;
-use std::io::BufRead;
+use std::io::Write;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::sync::Arc;
 use std::thread;
 use std::time;
+use store::Store;
+use trigger::Trigger;
+use waterfall::Waterfall;
 
 fn current_time() -> u64 {
     // in seconds
@@ -27,80 +49,102 @@ fn current_time() -> u64 {
     time.as_secs() * 1000 + time.subsec_nanos() as u64 / 1_000_000
 }
 
-fn read_data(
-    data: Arc<Mutex<BTreeMap<OrderedFloat<f32>, Vec<f32>>>>,
-    data_config: Arc<DataConfig>,
-    stop_signal: Arc<AtomicBool>,
-) {
-    let stdin = io::stdin();
+// The next visible channel after the given one, wrapping around. If no channel is given, the search
+// starts from the first or the last one.
+fn next_visible_channel(
+    channel: Option<usize>,
+    visible: &[bool],
+    backwards: bool,
+) -> Option<usize> {
+    let channels = visible.len();
+    let step = if backwards { channels - 1 } else { 1 };
+    let start = channel.unwrap_or(if backwards { 0 } else { channels - 1 });
 
-    for line in stdin.lock().lines() {
-        if stop_signal.load(Ordering::SeqCst) {
-            break;
-        }
-
-        let parts: Vec<u64> = line
-            .unwrap()
-            .trim_end()
-            .split(' ')
-            .map(|x| x.parse::<u64>().unwrap())
-            .collect();
-
-        assert_eq!(
-            data_config.y.len(),
-            parts.len() - 1,
-            "Data configuration specifies {} data inputs, but got {}.",
-            data_config.y.len(),
-            parts.len() - 1
-        );
-
-        let time = parts[0] as f32;
-
-        // Transform time to grid units.
-        let time = time
-            / data_config.grid.time.seconds_per_division
-            / data_config.grid.time.raw_per_second;
-
-        // Transform data to grid units.
-        let y: Vec<f32> = parts[1..]
-            .iter()
-            .enumerate()
-            .map(|(i, &y)| {
-                (y as f32 - data_config.y[i].raw_offset) / data_config.y[i].raw_per_division
-            })
-            .collect();
-
-        data.lock().unwrap().insert(OrderedFloat(time), y);
-    }
+    (1..=channels)
+        .map(|k| (start + k * step) % channels)
+        .find(|&i| visible[i])
 }
 
 fn main() {
     // Load settings and configuration files.
     let settings = config::parse_cli_options();
 
-    let data_config: Arc<DataConfig> = Arc::new(config::read_data_config(&settings.data_config));
+    let mut data_config = config::read_data_config(&settings.data_config);
+    if let Some(format) = settings.input_format {
+        data_config.input.format = format;
+    }
+    if let Some(source) = &settings.source {
+        data_config.input.source = Some(source.clone());
+    }
+    if let Some(replay) = &settings.replay {
+        assert!(
+            settings.speed >= playback::MIN_SPEED && settings.speed <= playback::MAX_SPEED,
+            "The replay speed must be between {} and {}.",
+            playback::MIN_SPEED,
+            playback::MAX_SPEED
+        );
+
+        data_config.input.source = Some(format!("file:{}", replay));
+        // The whole recording is kept, so that it can be seeked through.
+        data_config.retention = Retention::default();
+    }
+    let data_config: Arc<DataConfig> = Arc::new(data_config);
     let color_scheme: ColorScheme = config::read_color_scheme(&settings.color_scheme);
 
-    // Create a thread-safe btree of data points that will be used to read and write incoming data.
-    let data = Arc::new(Mutex::new(BTreeMap::new()));
+    let status = Arc::new(Status::new());
+    let error_log: Option<Box<dyn Write + Send>> = match settings.error_log.as_deref() {
+        Some("stderr") => Some(Box::new(io::stderr())),
+        Some(filename) => Some(Box::new(std::fs::File::create(filename).unwrap())),
+        None => None,
+    };
+
+    // The store of data points is owned by the render loop. The input thread parses incoming data
+    // and sends it over in chunks, so the two never contend on a lock.
+    let mut data = Store::new(&data_config);
+    let (sender, receiver) = mpsc::channel();
+
+    let source = match source::open(
+        data_config.input.source.as_deref().unwrap_or("stdin"),
+        &data_config,
+        status.clone(),
+    ) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Error opening the data source: {}", error);
+            return;
+        }
+    };
 
     // Create a child thread that will read data from the data source.
-    // data_write is a reference to the data that will be used by the input thread for writing the incoming data to
     let stop_signal = Arc::new(AtomicBool::new(false));
     let input_thread = thread::spawn({
-        let data = data.clone();
-        let data_config = data_config.clone();
+        let pipeline = ingest::Pipeline::new(data_config.clone(), status.clone(), error_log);
         let stop_signal = stop_signal.clone();
-        move || read_data(data, data_config, stop_signal)
+        move || ingest::run(source, pipeline, sender, stop_signal)
     });
 
     // Graphics.
     let background_color = renderer::Color::from(&color_scheme.background);
     let grid_color = renderer::Color::from(&color_scheme.grid);
-    let channel_colors: Vec<renderer::Color> = color_scheme
-        .channel
+    // The colors of the color scheme are repeated if there are more channels than colors.
+    let channel_colors: Vec<renderer::Color> = (0..data_config.y.len())
+        .map(|i| renderer::Color::from(&color_scheme.channel[i % color_scheme.channel.len()]))
+        .collect();
+
+    // Unfiltered values are drawn after all the channels, in a fainter color of their channel.
+    let trace_channels: Vec<usize> = (0..data_config.y.len())
+        .chain((0..data_config.y.len()).filter(|&i| data_config.y[i].show_raw))
+        .collect();
+    let trace_colors: Vec<renderer::Color> = trace_channels
         .iter()
-        .map(|channel_color| renderer::Color::from(channel_color))
+        .enumerate()
+        .map(|(k, &i)| match k < data_config.y.len() {
+            true => channel_colors[i],
+            false => renderer::Color {
+                a: channel_colors[i].a * 0.35,
+                ..channel_colors[i]
+            },
+        })
         .collect();
 
     // Initialize the window.
@@ -114,92 +158,288 @@ fn main() {
     // Initalize the shaders.
     let shader_program = renderer::initialize_shaders();
     let text_shader_program = renderer::initialize_text_shaders();
+    let image_shader_program = renderer::initialize_image_shaders();
 
-    // Transformation matrix for transforming from grid coordinates to OpenGL coordinates.
-    let coordinate_transform = renderer::transformation_matrix(
-        [
-            data_config.grid.time.divisions as f32 / 2.0,
-            data_config.grid.data.zero_shift,
-        ],
+    // Channels over time are drawn in panes stacked on top of each other, which share the time axis
+    // but each have their own divisions and zero.
+    let panes = plot::get_panes(&data_config);
+
+    // Transformation matrix for transforming from grid coordinates to OpenGL coordinates. Samples
+    // are stored in the grid units of the data config, so when the scales are changed by autoset,
+    // the plot spans a different number of them.
+    let generate_coordinate_transform = |scales: &Scales, divisions: u32, zero_shift: f32| {
+        renderer::transformation_matrix(
+            [scales.time_span(&data_config) / 2.0, zero_shift],
+            [2.0 / scales.time_span(&data_config), 2.0 / divisions as f32],
+        )
+    };
+    let generate_coordinate_transforms = |scales: &Scales| -> Vec<Matrix3<f32>> {
+        panes
+            .iter()
+            .map(|pane| generate_coordinate_transform(scales, pane.divisions, pane.zero_shift))
+            .collect()
+    };
+    let mut scales = Scales::new(&data_config);
+    let mut coordinate_transforms = generate_coordinate_transforms(&scales);
+
+    // In XY mode, one channel is plotted against another on the same grid, centered horizontally.
+    let xy_channels = match &data_config.xy {
+        Some(xy) => (
+            data_config.channel_index(&xy.x).unwrap(),
+            data_config.channel_index(&xy.y).unwrap(),
+        ),
+        None => (0, cmp::min(1, data_config.y.len() - 1)),
+    };
+    let xy_transform = renderer::transformation_matrix(
+        [0.0, data_config.grid.data.zero_shift],
         [
             2.0 / data_config.grid.time.divisions as f32,
             2.0 / data_config.grid.data.divisions as f32,
         ],
     );
+    let mut xy_mode = data_config.xy.is_some();
 
     let identity: Matrix3<f32> = Matrix3::identity();
     let zero_vector: Vector2<f32> = Vector2::zeros();
 
-    let grid = plot::generate_grid(
-        data_config.grid.time.divisions,
-        data_config.grid.data.divisions,
-        grid_color,
-    );
-
-    let (_, _, grid_width, grid_height) = plot::get_dimensions(
+    let pane_grids: Vec<plot::Mesh> = panes
+        .iter()
+        .map(|pane| {
+            plot::generate_grid(data_config.grid.time.divisions, pane.divisions, grid_color)
+        })
+        .collect();
+    let pane_dimensions = plot::get_pane_dimensions(
         settings.width,
         settings.height,
         settings.padding,
         data_config.grid.time.divisions,
-        data_config.grid.data.divisions,
+        &panes,
     );
 
-    let grid_label = text::generate_text(
-        0.0,
-        1.0 - (settings.height - grid_height as u32) as f32 / settings.height as f32 / 2.0,
-        &data_config.grid.label,
-        settings.padding as f32 / 1.5,
-        &font,
+    // All of the panes together, which the labels over time are placed around.
+    let stack_dimensions = plot::get_dimensions(
         settings.width,
         settings.height,
-        renderer::Color::from(&color_scheme.labels),
-        text::Orientation::Horizontal,
+        settings.padding,
+        data_config.grid.time.divisions,
+        panes.iter().map(|pane| pane.divisions).sum(),
     );
 
-    let grid_data_label = text::generate_text(
-        -1.0 + (settings.width - grid_width as u32) as f32 / settings.width as f32 / 2.0,
-        0.0,
-        &data_config.grid.data.label,
-        settings.padding as f32 / 1.5,
-        &font,
+    // The other modes are drawn on the data grid.
+    let grid = plot::generate_grid(
+        data_config.grid.time.divisions,
+        data_config.grid.data.divisions,
+        grid_color,
+    );
+    let grid_dimensions = plot::get_dimensions(
         settings.width,
         settings.height,
-        renderer::Color::from(&color_scheme.labels),
-        text::Orientation::Vertical,
+        settings.padding,
+        data_config.grid.time.divisions,
+        data_config.grid.data.divisions,
     );
 
-    let grid_time_label = text::generate_text(
+    // Vertical centers of the panes in OpenGL coordinates.
+    let pane_centers: Vec<f32> = pane_dimensions
+        .iter()
+        .map(|&(_, y, _, height)| {
+            (y as f32 + height as f32 / 2.0) / settings.height as f32 * 2.0 - 1.0
+        })
+        .collect();
+
+    // The title above the grid is replaced by the results of approximations and the peaks of the
+    // spectrum. The labels are placed around the grid with the given dimensions.
+    let generate_title_label = |label: &str, (_, _, _, height): (i32, i32, i32, i32)| {
+        text::generate_text(
+            0.0,
+            1.0 - (settings.height - height as u32) as f32 / settings.height as f32 / 2.0,
+            label,
+            settings.padding as f32 / 1.5,
+            &font,
+            settings.width,
+            settings.height,
+            renderer::Color::from(&color_scheme.labels),
+            text::Orientation::Horizontal,
+            text::Alignment::Center,
+        )
+    };
+    let grid_label = generate_title_label(&data_config.grid.label, stack_dimensions);
+    let xy_grid_label = generate_title_label(&data_config.grid.label, grid_dimensions);
+
+    let generate_grid_data_label =
+        |label: &str, center: f32, (_, _, width, _): (i32, i32, i32, i32)| {
+            text::generate_text(
+                -1.0 + (settings.width - width as u32) as f32 / settings.width as f32 / 2.0,
+                center,
+                label,
+                settings.padding as f32 / 1.5,
+                &font,
+                settings.width,
+                settings.height,
+                renderer::Color::from(&color_scheme.labels),
+                text::Orientation::Vertical,
+                text::Alignment::Center,
+            )
+        };
+
+    let generate_grid_time_label = |label: &str, (_, _, _, height): (i32, i32, i32, i32)| {
+        text::generate_text(
+            0.0,
+            -1.0 + (settings.height - height as u32) as f32 / settings.height as f32 / 2.0,
+            label,
+            settings.padding as f32 / 1.5,
+            &font,
+            settings.width,
+            settings.height,
+            renderer::Color::from(&color_scheme.labels),
+            text::Orientation::Horizontal,
+            text::Alignment::Center,
+        )
+    };
+
+    // The labels of the axes show the channels on them in XY mode, and the scales picked by autoset
+    // instead of the configured ones. Over time, every pane has its own data label.
+    let generate_time_label_string = |scales: &Scales, autoset: bool| match autoset {
+        true => scales.time_label(),
+        false => data_config.grid.time.label.clone(),
+    };
+    let generate_axis_labels = |scales: &Scales, autoset: bool, xy_mode: bool| {
+        let time_label = match xy_mode {
+            true => scales.channel_label(&data_config, xy_channels.0),
+            false => generate_time_label_string(scales, autoset),
+        };
+
+        let data_labels: Vec<text::Text> = match xy_mode {
+            true => vec![generate_grid_data_label(
+                &scales.channel_label(&data_config, xy_channels.1),
+                0.0,
+                grid_dimensions,
+            )],
+            false => panes
+                .iter()
+                .zip(&pane_centers)
+                .map(|(pane, &center)| {
+                    let label = match autoset {
+                        true => scales.data_label(&data_config, &pane.channels),
+                        false => pane.label.clone(),
+                    };
+
+                    generate_grid_data_label(&label, center, stack_dimensions)
+                })
+                .collect(),
+        };
+
+        let dimensions = match xy_mode {
+            true => grid_dimensions,
+            false => stack_dimensions,
+        };
+        (
+            generate_grid_time_label(&time_label, dimensions),
+            data_labels,
+        )
+    };
+    let (mut grid_time_label, mut grid_data_labels) = generate_axis_labels(&scales, false, xy_mode);
+
+    let generate_zero_label = |center: f32,
+                               (_, _, width, height): (i32, i32, i32, i32),
+                               divisions: u32,
+                               zero_shift: f32| {
+        text::generate_text(
+            1.0 - (settings.width - width as u32) as f32 / settings.width as f32 / 2.0,
+            center + (zero_shift / divisions as f32 * height as f32) / settings.height as f32 * 2.0,
+            "0",
+            settings.padding as f32 / 1.5,
+            &font,
+            settings.width,
+            settings.height,
+            renderer::Color::from(&color_scheme.labels),
+            text::Orientation::Horizontal,
+            text::Alignment::Center,
+        )
+    };
+    let zero_label = generate_zero_label(
         0.0,
-        -1.0 + (settings.height - grid_height as u32) as f32 / settings.height as f32 / 2.0,
-        &data_config.grid.time.label,
-        settings.padding as f32 / 1.5,
-        &font,
-        settings.width,
-        settings.height,
-        renderer::Color::from(&color_scheme.labels),
-        text::Orientation::Horizontal,
+        grid_dimensions,
+        data_config.grid.data.divisions,
+        data_config.grid.data.zero_shift,
     );
+    let pane_zero_labels: Vec<text::Text> = panes
+        .iter()
+        .zip(&pane_dimensions)
+        .zip(&pane_centers)
+        .map(|((pane, &dimensions), &center)| {
+            generate_zero_label(center, dimensions, pane.divisions, pane.zero_shift)
+        })
+        .collect();
 
-    let zero_label = text::generate_text(
-        1.0 - (settings.width - grid_width as u32) as f32 / settings.width as f32 / 2.0,
-        (data_config.grid.data.zero_shift / data_config.grid.data.divisions as f32
-            * grid_height as f32)
-            / settings.height as f32
-            * 2.0,
-        "0",
-        settings.padding as f32 / 1.5,
-        &font,
-        settings.width,
-        settings.height,
-        renderer::Color::from(&color_scheme.labels),
-        text::Orientation::Horizontal,
-    );
+    // The legend in the top right corner names each channel in its color. The focused channel is
+    // marked with an arrow, and hidden channels are faded.
+    let generate_legend = |focused_channel: Option<usize>, visible: &[bool]| -> Vec<text::Text> {
+        data_config
+            .y
+            .iter()
+            .enumerate()
+            .map(|(i, y)| {
+                let marker = if focused_channel == Some(i) {
+                    "▶"
+                } else {
+                    "■"
+                };
+                let name = y.name.clone().unwrap_or_else(|| format!("y{}", i + 1));
+                let entry = match &y.unit {
+                    Some(unit) => format!("{} {} ({})", marker, name, unit),
+                    None => format!("{} {}", marker, name),
+                };
+                let color = match visible[i] {
+                    true => channel_colors[i],
+                    false => renderer::Color {
+                        a: channel_colors[i].a * 0.3,
+                        ..channel_colors[i]
+                    },
+                };
+
+                text::generate_text(
+                    1.0 - settings.padding as f32 / settings.width as f32,
+                    1.0 - settings.padding as f32 / 4.0 / settings.height as f32
+                        * (1.0 + 1.5 * i as f32),
+                    &entry,
+                    settings.padding as f32 / 4.0,
+                    &font,
+                    settings.width,
+                    settings.height,
+                    color,
+                    text::Orientation::Horizontal,
+                    text::Alignment::Right,
+                )
+            })
+            .collect()
+    };
+
+    // The status area in the top left corner is regenerated only when its contents change.
+    let mut status_summary = None;
+    let mut status_label = None;
+    let mut status_updated = time::Instant::now();
 
     // Main loop.
     let mut time_started = None;
+    let mut playback: Option<Playback> = None;
     let mut frozen_translation: Option<f64> = None;
-    //let mut focused_channel = None;
+    let mut autoset = false;
+    let mut trigger = Trigger::new(&data_config);
+    let mut spectrum = Spectrum::new(&data_config);
+    let mut spectrum_mode = false;
+    // Like the status, the labels of the spectrum are regenerated only a few times a second.
+    let mut spectrum_summary = None;
+    let mut spectrum_labels = None;
+    let mut spectrum_updated = time::Instant::now();
+    let mut waterfall = Waterfall::new(&data_config);
+    let mut waterfall_mode = false;
+    let mut waterfall_summary = None;
+    let mut waterfall_labels = None;
+    let mut waterfall_updated = time::Instant::now();
+    let mut visible = vec![true; data_config.y.len()];
     let mut focused_channel = Some(0);
+    let mut legend = generate_legend(focused_channel, &visible);
     let mut approximation_type = None;
     let mut approximation_range = None;
     let mut approximation_label = None;
@@ -214,26 +454,102 @@ fn main() {
         shader_program.set_uniform_matrix("coordinate_transform", &identity);
         shader_program.set_uniform_vector("translation", &zero_vector);
 
-        plot::draw_grid(
-            &grid,
-            settings.width,
-            settings.height,
-            settings.padding,
-            data_config.grid.time.divisions,
-            data_config.grid.data.divisions,
-        );
+        match xy_mode || spectrum_mode || waterfall_mode {
+            true => plot::draw_grid(&grid, grid_dimensions),
+            false => {
+                for (grid, &dimensions) in pane_grids.iter().zip(&pane_dimensions) {
+                    plot::draw_grid(grid, dimensions);
+                }
+            }
+        }
 
-        // Lock the mutex so that we can safely access the data.
-        let data = data.lock().unwrap();
+        // Take in everything the input thread has read since the last frame.
+        for chunk in receiver.try_iter() {
+            data.extend(chunk);
+        }
 
-        // If we have no points, there's nothing to draw.
+        let replay_summary = playback.as_ref().map(|playback| playback.summary(&data));
+        let summary: Vec<String> = vec![
+            replay_summary,
+            trigger.summary(&data_config),
+            status.summary(),
+            data.summary(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let summary = match summary.is_empty() {
+            true => None,
+            false => Some(summary.join("   ")),
+        };
+
+        // Regenerating text is expensive, so the status is updated only a few times a second.
+        if summary != status_summary && status_updated.elapsed() > time::Duration::from_millis(250)
+        {
+            status_label = summary.as_ref().map(|summary| {
+                text::generate_text(
+                    -1.0 + settings.padding as f32 / settings.width as f32,
+                    1.0 - settings.padding as f32 / 4.0 / settings.height as f32,
+                    summary,
+                    settings.padding as f32 / 4.0,
+                    &font,
+                    settings.width,
+                    settings.height,
+                    renderer::Color::from(&color_scheme.labels),
+                    text::Orientation::Horizontal,
+                    text::Alignment::Left,
+                )
+            });
+            status_summary = summary;
+            status_updated = time::Instant::now();
+        }
+
+        // If we have no points, there's no graphs to draw, but the labels and the status are still
+        // shown and the program can still be stopped.
         if data.len() == 0 {
+            text_shader_program.set_used();
+            text_shader_program.set_uniform_texture("textTexture", gl::TEXTURE0 as i32);
+
+            match xy_mode {
+                true => text::draw_text(&xy_grid_label, settings.width, settings.height),
+                false => text::draw_text(&grid_label, settings.width, settings.height),
+            }
+            text::draw_text(&grid_time_label, settings.width, settings.height);
+            grid_data_labels
+                .iter()
+                .for_each(|label| text::draw_text(label, settings.width, settings.height));
+            legend
+                .iter()
+                .for_each(|entry| text::draw_text(entry, settings.width, settings.height));
+            if let Some(label) = &status_label {
+                text::draw_text(label, settings.width, settings.height);
+            }
+
+            window.swap_buffers();
+            glfw.poll_events();
+
+            for (_, event) in glfw::flush_messages(&events) {
+                if let glfw::WindowEvent::Key(Key::Q, _, Action::Press, _) = event {
+                    window.set_should_close(true);
+                    stop_signal.store(true, Ordering::SeqCst);
+                }
+            }
+
             continue;
         }
 
+        // A recording is played back from its first sample, independently of the wall clock.
+        if settings.replay.is_some() && playback.is_none() {
+            playback = Some(Playback::new(
+                data.first().unwrap().into_inner() as f64,
+                settings.speed,
+                data_config.grid.time.seconds_per_division,
+            ));
+        }
+
         // Here is where we start the clock to ensure proper translation when drawing the graphs.
         if let None = time_started {
-            let (min, _) = data.iter().next().unwrap();
+            let min = data.first().unwrap();
 
             // Align the time so that the right side of the plot is the beginning. Use the minimum
             // value from the data that we are drawing to determine the shift.
@@ -244,29 +560,45 @@ fn main() {
         }
 
         // How much time had passed determines the translation when drawing the graphs.
-        let time_passed = match frozen_translation {
-            Some(time_passed) => time_passed,
-            None => {
+        let time_passed = match (&mut playback, frozen_translation) {
+            (Some(playback), _) => playback.update(&data),
+            (None, Some(time_passed)) => time_passed,
+            (None, None) => {
                 current_time() as f64 / data_config.grid.time.seconds_per_division as f64 / 1000f64
                     - time_started.unwrap()
             }
         };
 
+        // An active trigger positions the plot on the edges of its channel instead. Freezing keeps
+        // the time the plot rolled to, so that the trigger keeps showing the same edge.
+        let time_rolled = time_passed;
+        let time_passed = trigger
+            .update(
+                &data,
+                &data_config,
+                time_rolled,
+                scales.time_span(&data_config),
+            )
+            .unwrap_or(time_rolled);
+
         // For optimizations purposes, make sure to draw only what is actually visible.
         // The code below specifies the range of points to draw based on how much time had passed.
         let range = cmp::max(
-            OrderedFloat(time_passed as f32 - data_config.grid.time.divisions as f32),
+            OrderedFloat(time_passed as f32 - scales.time_span(&data_config)),
             OrderedFloat(0.0),
         )..OrderedFloat(time_passed as f32);
 
-        let graphs = plot::generate_graphs(
-            &data,
-            &range,
-            data_config.y.len(),
-            &channel_colors,
-            &approximation_range,
-            &focused_channel,
-        );
+        let graphs = match xy_mode || spectrum_mode || waterfall_mode {
+            true => vec![],
+            false => plot::generate_graphs(
+                &data,
+                &range,
+                trace_colors.len(),
+                &trace_colors,
+                &approximation_range,
+                &focused_channel,
+            ),
+        };
 
         // Set the coordinate transformation matrix and the time translation vector for use in the
         // shader. This means that the GPU will handle all the transformations and therefore will be more
@@ -288,11 +620,32 @@ fn main() {
             let (transformed_coefficients, transformed_errors) =
                 approximation::transform_coefficients(&coefficients, &errors, approximation_type);
 
+            // Show the results in seconds and the unit of the channel rather than in divisions.
+            let (transformed_coefficients, transformed_errors) = approximation::to_physical_units(
+                &transformed_coefficients,
+                &transformed_errors,
+                approximation_type,
+                &data_config.y[*i],
+                data_config.grid.time.seconds_per_division,
+            );
+
+            let unit = data_config.y[*i].unit.as_deref().unwrap_or("");
+            let units = match approximation_type {
+                approximation::Type::Constant => vec![String::from(unit)],
+                approximation::Type::Linear => vec![String::from("s"), format!("{}/s", unit)],
+                approximation::Type::Quadratic => vec![
+                    String::from(unit),
+                    String::from("s"),
+                    format!("{}/s²", unit),
+                ],
+            };
+
             let measurement_strings: Vec<String> = transformed_coefficients
                 .iter()
                 .zip(&transformed_errors)
-                .map(|(&coefficient, &error)| {
-                    approximation::measurement_to_string(coefficient, error)
+                .zip(&units)
+                .map(|((&coefficient, &error), unit)| {
+                    approximation::measurement_to_string(coefficient, error, unit)
                 })
                 .collect();
 
@@ -314,16 +667,9 @@ fn main() {
                 }
             };
 
-            approximation_label = Some(text::generate_text(
-                0.0,
-                1.0 - (settings.height - grid_height as u32) as f32 / settings.height as f32 / 2.0,
+            approximation_label = Some(generate_title_label(
                 &approximation_label_string,
-                settings.padding as f32 / 1.5,
-                &font,
-                settings.width,
-                settings.height,
-                renderer::Color::from(&color_scheme.labels),
-                text::Orientation::Horizontal,
+                stack_dimensions,
             ));
 
             let graph = plot::generate_polynomial_graph(
@@ -332,37 +678,185 @@ fn main() {
                 renderer::Color::from(&color_scheme.fit),
             );
 
-            Some(graph)
+            Some((graph, *i))
         } else {
             None
         };
 
-        // Free the mutex as we no longer need the data after generating the graphs.
-        drop(data);
-
-        shader_program.set_uniform_matrix("coordinate_transform", &coordinate_transform);
+        // Every channel is drawn in its panes, with its own scale.
         shader_program.set_uniform_vector("translation", &Vector2::new(-time_passed as f32, 0.0));
-        graphs.iter().for_each(|graph| plot::draw_graph(graph));
+        for ((pane, &dimensions), coordinate_transform) in panes
+            .iter()
+            .zip(&pane_dimensions)
+            .zip(&coordinate_transforms)
+        {
+            plot::set_viewport(dimensions);
+
+            for (graph, &i) in graphs.iter().zip(&trace_channels) {
+                if visible[i] && pane.channels.contains(&i) {
+                    shader_program.set_uniform_matrix(
+                        "coordinate_transform",
+                        &(coordinate_transform * scales.channel_transform(&data_config, i)),
+                    );
+                    plot::draw_graph(graph);
+                }
+            }
+
+            if let Some((graph, i)) = &polynomial_graph {
+                if pane.channels.contains(i) {
+                    shader_program.set_uniform_matrix(
+                        "coordinate_transform",
+                        &(coordinate_transform * scales.channel_transform(&data_config, *i)),
+                    );
+                    plot::draw_graph(graph);
+                }
+            }
+        }
+
+        plot::set_viewport(grid_dimensions);
+        if spectrum_mode {
+            let channel = focused_channel.unwrap_or(0);
+
+            if let Some(magnitudes) = spectrum.compute(&data, &data_config, &range, channel) {
+                let peaks = spectrum.peaks(&magnitudes);
+                let markers: Vec<(f32, f32)> = peaks
+                    .iter()
+                    .map(|&(frequency, amplitude)| {
+                        spectrum.position(&magnitudes, &data_config, frequency, amplitude)
+                    })
+                    .collect();
+
+                let graph = plot::generate_line(
+                    &spectrum.points(&magnitudes, &data_config),
+                    channel_colors[channel],
+                );
+                let markers =
+                    plot::generate_markers(&markers, renderer::Color::from(&color_scheme.fit));
 
-        if let Some(graph) = polynomial_graph {
+                // The spectrum is laid out on the grid directly.
+                shader_program.set_uniform_matrix("coordinate_transform", &identity);
+                shader_program.set_uniform_vector("translation", &zero_vector);
+                plot::draw_graph(&graph);
+                plot::draw_graph(&markers);
+
+                let summary = (
+                    spectrum.frequency_label(&magnitudes, &data_config),
+                    spectrum.amplitude_label(&magnitudes, &data_config),
+                    spectrum.peaks_label(&peaks, &data_config),
+                );
+                if Some(&summary) != spectrum_summary.as_ref()
+                    && spectrum_updated.elapsed() > time::Duration::from_millis(250)
+                {
+                    spectrum_labels = Some((
+                        generate_grid_time_label(&summary.0, grid_dimensions),
+                        generate_grid_data_label(&summary.1, 0.0, grid_dimensions),
+                        generate_title_label(&summary.2, grid_dimensions),
+                    ));
+                    spectrum_summary = Some(summary);
+                    spectrum_updated = time::Instant::now();
+                }
+            }
+        } else if waterfall_mode {
+            let channel = focused_channel.unwrap_or(0);
+            waterfall.update(
+                &data,
+                &data_config,
+                time_passed,
+                scales.time_span(&data_config),
+                channel,
+                spectrum.window(),
+            );
+
+            // The waterfall scrolls along with the graphs, so it's drawn with the same
+            // transformations.
+            image_shader_program.set_used();
+            image_shader_program.set_uniform_matrix(
+                "coordinate_transform",
+                &generate_coordinate_transform(
+                    &scales,
+                    data_config.grid.data.divisions,
+                    data_config.grid.data.zero_shift,
+                ),
+            );
+            image_shader_program
+                .set_uniform_vector("translation", &Vector2::new(-time_passed as f32, 0.0));
+            image_shader_program.set_uniform_texture("imageTexture", gl::TEXTURE0 as i32);
+            waterfall.draw();
+
+            let summary = (
+                waterfall.frequency_label(),
+                waterfall.level_label(&data_config),
+                generate_time_label_string(&scales, autoset),
+            );
+            if Some(&summary) != waterfall_summary.as_ref()
+                && waterfall_updated.elapsed() > time::Duration::from_millis(250)
+            {
+                waterfall_labels = Some((
+                    generate_grid_data_label(&summary.0, 0.0, grid_dimensions),
+                    generate_title_label(&summary.1, grid_dimensions),
+                    generate_grid_time_label(&summary.2, grid_dimensions),
+                ));
+                waterfall_summary = Some(summary);
+                waterfall_updated = time::Instant::now();
+            }
+        } else if xy_mode {
+            let (x, y) = xy_channels;
+            let graph = plot::generate_xy_graph(&data, &range, x, y, channel_colors[y]);
+
+            shader_program.set_uniform_vector("translation", &zero_vector);
+            shader_program.set_uniform_matrix(
+                "coordinate_transform",
+                &(xy_transform * scales.xy_transform(&data_config, x, y)),
+            );
             plot::draw_graph(&graph);
         }
 
         text_shader_program.set_used();
         text_shader_program.set_uniform_texture("textTexture", gl::TEXTURE0 as i32);
 
-        match &approximation_label {
-            Some(label) => {
-                text::draw_text(&label, settings.width, settings.height);
+        if spectrum_mode {
+            if let Some((time_label, data_label, peaks_label)) = &spectrum_labels {
+                text::draw_text(peaks_label, settings.width, settings.height);
+                text::draw_text(time_label, settings.width, settings.height);
+                text::draw_text(data_label, settings.width, settings.height);
             }
-            None => {
-                text::draw_text(&grid_label, settings.width, settings.height);
+        } else if waterfall_mode {
+            // The waterfall shares the time axis with the graphs.
+            if let Some((frequency_label, level_label, time_label)) = &waterfall_labels {
+                text::draw_text(level_label, settings.width, settings.height);
+                text::draw_text(frequency_label, settings.width, settings.height);
+                text::draw_text(time_label, settings.width, settings.height);
             }
-        };
+        } else {
+            match &approximation_label {
+                Some(label) => {
+                    text::draw_text(&label, settings.width, settings.height);
+                }
+                None => match xy_mode {
+                    true => text::draw_text(&xy_grid_label, settings.width, settings.height),
+                    false => text::draw_text(&grid_label, settings.width, settings.height),
+                },
+            };
 
-        text::draw_text(&grid_time_label, settings.width, settings.height);
-        text::draw_text(&grid_data_label, settings.width, settings.height);
-        text::draw_text(&zero_label, settings.width, settings.height);
+            text::draw_text(&grid_time_label, settings.width, settings.height);
+            grid_data_labels
+                .iter()
+                .for_each(|label| text::draw_text(label, settings.width, settings.height));
+            // Autoset gives every channel its own offset, so there's no common zero.
+            match (autoset, xy_mode) {
+                (true, _) => {}
+                (false, true) => text::draw_text(&zero_label, settings.width, settings.height),
+                (false, false) => pane_zero_labels
+                    .iter()
+                    .for_each(|label| text::draw_text(label, settings.width, settings.height)),
+            }
+        }
+        legend
+            .iter()
+            .for_each(|entry| text::draw_text(entry, settings.width, settings.height));
+        if let Some(label) = &status_label {
+            text::draw_text(label, settings.width, settings.height);
+        }
 
         // Display the image the GPU drew.
         window.swap_buffers();
@@ -391,14 +885,72 @@ fn main() {
                             approximation_label = None;
                         }
                         None => {
-                            frozen_translation = Some(time_passed);
+                            frozen_translation = Some(time_rolled);
                             approximation_range = Some(range.clone());
                         }
                     };
+
+                    // Freezing a replay pauses it, so that it continues from the same place.
+                    if let Some(playback) = &mut playback {
+                        playback.set_paused(frozen_translation.is_some());
+                    }
+                }
+                // Step through a paused replay one sample at a time.
+                glfw::WindowEvent::Key(
+                    key @ (Key::Period | Key::Comma),
+                    _,
+                    Action::Press | Action::Repeat,
+                    _,
+                ) => {
+                    if let Some(playback) = &mut playback {
+                        if playback.paused() {
+                            let position = playback.position();
+                            playback.step(key == Key::Period, &data);
+
+                            // The approximation window moves along with the plot.
+                            if let Some(approximation_range) = &mut approximation_range {
+                                approximation_range.start +=
+                                    (playback.position() - position) as f32;
+                                approximation_range.end += (playback.position() - position) as f32;
+                            }
+                        }
+                    }
+                }
+                // Seek a replay forward and back by the width of the plot.
+                glfw::WindowEvent::Key(
+                    key @ (Key::Right | Key::Left),
+                    _,
+                    Action::Press | Action::Repeat,
+                    _,
+                ) => {
+                    if let Some(playback) = &mut playback {
+                        let position = playback.position();
+                        let width = scales.time_span(&data_config) as f64;
+                        playback.seek(if key == Key::Right { width } else { -width }, &data);
+
+                        if let Some(approximation_range) = &mut approximation_range {
+                            approximation_range.start += (playback.position() - position) as f32;
+                            approximation_range.end += (playback.position() - position) as f32;
+                        }
+                    }
+                }
+                // Double or halve the replay speed.
+                glfw::WindowEvent::Key(Key::RightBracket, _, Action::Press, _) => {
+                    if let Some(playback) = &mut playback {
+                        playback.set_speed(playback.speed() * 2.0);
+                    }
+                }
+                glfw::WindowEvent::Key(Key::LeftBracket, _, Action::Press, _) => {
+                    if let Some(playback) = &mut playback {
+                        playback.set_speed(playback.speed() / 2.0);
+                    }
                 }
                 // Cycle through approximations modes. Off, constant, linear, quadratic.
                 glfw::WindowEvent::Key(Key::M, _, Action::Press, _) => {
-                    if let Some(_) = frozen_translation {
+                    if let (Some(_), false) = (
+                        frozen_translation,
+                        xy_mode || spectrum_mode || waterfall_mode,
+                    ) {
                         match approximation_type {
                             None => {
                                 approximation_type = Some(approximation::Type::Constant);
@@ -478,60 +1030,153 @@ fn main() {
                         }
                     }
                 }
-                // Use numbers 1..9 to focus channels. 0 to unfocus.
-                //glfw::WindowEvent::Key(Key::Num0, _, Action::Press, _) => {
-                //focused_channel = None;
-                //}
-                //glfw::WindowEvent::Key(Key::Num1, _, Action::Press, _) => {
-                //if data_config.y.len() >= 1 {
-                //focused_channel = Some(0);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num2, _, Action::Press, _) => {
-                //if data_config.y.len() >= 2 {
-                //focused_channel = Some(1);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num3, _, Action::Press, _) => {
-                //if data_config.y.len() >= 3 {
-                //focused_channel = Some(2);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num4, _, Action::Press, _) => {
-                //if data_config.y.len() >= 4 {
-                //focused_channel = Some(3);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num5, _, Action::Press, _) => {
-                //if data_config.y.len() >= 5 {
-                //focused_channel = Some(4);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num6, _, Action::Press, _) => {
-                //if data_config.y.len() >= 6 {
-                //focused_channel = Some(5);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num7, _, Action::Press, _) => {
-                //if data_config.y.len() >= 7 {
-                //focused_channel = Some(6);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num8, _, Action::Press, _) => {
-                //if data_config.y.len() >= 8 {
-                //focused_channel = Some(7);
-                //}
-                //}
-                //glfw::WindowEvent::Key(Key::Num9, _, Action::Press, _) => {
-                //if data_config.y.len() >= 9 {
-                //focused_channel = Some(8);
-                //}
-                //}
+                // Pick scales that fit the recent data, like the autoset of an oscilloscope, and
+                // Shift+A to go back to the scales in the data config.
+                glfw::WindowEvent::Key(Key::A, _, Action::Press, modifiers) => {
+                    autoset = !modifiers.contains(glfw::Modifiers::Shift);
+
+                    scales = match autoset {
+                        true => {
+                            let channel = focused_channel.unwrap_or(0);
+                            autoset::autoset(&data, &data_config, &panes, &scales, channel)
+                        }
+                        false => Scales::new(&data_config),
+                    };
+
+                    coordinate_transforms = generate_coordinate_transforms(&scales);
+                    let (time_label, data_labels) = generate_axis_labels(&scales, autoset, xy_mode);
+                    grid_time_label = time_label;
+                    grid_data_labels = data_labels;
+                }
+                // X switches between plotting the channels over time and against each other.
+                // Approximations are fitted over time, so they're turned off.
+                glfw::WindowEvent::Key(Key::X, _, Action::Press, _) => {
+                    xy_mode = !xy_mode;
+                    approximation_type = None;
+                    approximation_label = None;
+
+                    let (time_label, data_labels) = generate_axis_labels(&scales, autoset, xy_mode);
+                    grid_time_label = time_label;
+                    grid_data_labels = data_labels;
+                }
+                // F switches to the spectrum of the focused channel and back, and Shift+F to its
+                // waterfall. W cycles through the window functions of the spectrum and D switches
+                // between a linear scale and dB.
+                glfw::WindowEvent::Key(Key::F, _, Action::Press, modifiers) => {
+                    if modifiers.contains(glfw::Modifiers::Shift) {
+                        waterfall_mode = !waterfall_mode;
+                        spectrum_mode = false;
+                    } else {
+                        spectrum_mode = !spectrum_mode;
+                        waterfall_mode = false;
+                        spectrum.reset();
+                    }
+
+                    approximation_type = None;
+                    approximation_label = None;
+                }
+                glfw::WindowEvent::Key(Key::W, _, Action::Press, _) => {
+                    if spectrum_mode {
+                        spectrum.next_window();
+                    }
+                }
+                glfw::WindowEvent::Key(Key::D, _, Action::Press, _) => {
+                    if spectrum_mode {
+                        spectrum.toggle_scale();
+                    }
+                }
+                // T cycles the trigger through its modes and off, and Shift+T through the slopes.
+                glfw::WindowEvent::Key(Key::T, _, Action::Press, modifiers) => {
+                    if modifiers.contains(glfw::Modifiers::Shift) {
+                        trigger.set_slope(match trigger.slope() {
+                            Slope::Rising => Slope::Falling,
+                            Slope::Falling => Slope::Either,
+                            Slope::Either => Slope::Rising,
+                        });
+                    } else {
+                        trigger.set_mode(match trigger.mode() {
+                            None => Some(TriggerMode::Auto),
+                            Some(TriggerMode::Auto) => Some(TriggerMode::Normal),
+                            Some(TriggerMode::Normal) => Some(TriggerMode::Single),
+                            Some(TriggerMode::Single) => None,
+                        });
+                    }
+                }
+                // R arms the trigger again, for another single sweep.
+                glfw::WindowEvent::Key(Key::R, _, Action::Press, _) => {
+                    trigger.arm();
+                }
+                // Up and down move the trigger level by a tenth of a division.
+                glfw::WindowEvent::Key(
+                    key @ (Key::Up | Key::Down),
+                    _,
+                    Action::Press | Action::Repeat,
+                    _,
+                ) => {
+                    trigger.step_level(key == Key::Up, &data_config);
+                }
+                // Tab focuses the next visible channel and Shift+Tab the previous one. The focused
+                // channel is the one approximations are fitted to.
+                glfw::WindowEvent::Key(Key::Tab, _, Action::Press | Action::Repeat, modifiers) => {
+                    let backwards = modifiers.contains(glfw::Modifiers::Shift);
+                    focused_channel = next_visible_channel(focused_channel, &visible, backwards);
+                    legend = generate_legend(focused_channel, &visible);
+                }
+                // 0 unfocuses all channels.
+                glfw::WindowEvent::Key(Key::Num0, _, Action::Press, _) => {
+                    focused_channel = None;
+                    approximation_label = None;
+                    legend = generate_legend(focused_channel, &visible);
+                }
+                // Numbers 1 to 9 show and hide the first nine channels, and V the focused one.
+                // Shift+V shows all of them again.
+                glfw::WindowEvent::Key(Key::V, _, Action::Press, modifiers)
+                    if modifiers.contains(glfw::Modifiers::Shift) =>
+                {
+                    visible = vec![true; data_config.y.len()];
+                    legend = generate_legend(focused_channel, &visible);
+                }
+                glfw::WindowEvent::Key(
+                    key @ (Key::Num1
+                    | Key::Num2
+                    | Key::Num3
+                    | Key::Num4
+                    | Key::Num5
+                    | Key::Num6
+                    | Key::Num7
+                    | Key::Num8
+                    | Key::Num9
+                    | Key::V),
+                    _,
+                    Action::Press,
+                    _,
+                ) => {
+                    let channel = match key {
+                        Key::V => focused_channel,
+                        key => Some((key as i32 - Key::Num1 as i32) as usize),
+                    };
+
+                    if let Some(i) = channel.filter(|&i| i < visible.len()) {
+                        visible[i] = !visible[i];
+
+                        // A hidden channel can't stay focused.
+                        if !visible[i] && focused_channel == Some(i) {
+                            focused_channel = next_visible_channel(Some(i), &visible, false);
+                            if focused_channel.is_none() {
+                                approximation_label = None;
+                            }
+                        }
+
+                        legend = generate_legend(focused_channel, &visible);
+                    }
+                }
                 _ => {}
             }
         }
     }
 
+    // Closing the window also stops the input thread.
+    stop_signal.store(true, Ordering::SeqCst);
     window.close();
 
     input_thread.join().unwrap();
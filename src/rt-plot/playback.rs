@@ -0,0 +1,123 @@
+use super::store::Store;
+use ordered_float::OrderedFloat;
+use std::time::Instant;
+
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+/// Moves the right edge of the plot through a recording according to its timestamps instead of the
+/// wall clock, so that it can be paused, stepped through and seeked.
+pub struct Playback {
+    // Right edge of the plot in grid units. Only samples before it are shown.
+    position: f64,
+    speed: f64,
+    seconds_per_division: f64,
+    paused: bool,
+    updated: Instant,
+}
+
+impl Playback {
+    pub fn new(start: f64, speed: f64, seconds_per_division: f32) -> Playback {
+        Playback {
+            position: start,
+            speed,
+            seconds_per_division: seconds_per_division as f64,
+            paused: false,
+            updated: Instant::now(),
+        }
+    }
+
+    // Playback stops a division after the last sample, so that it's still visible.
+    fn clamp(&mut self, data: &Store) {
+        if let (Some(first), Some(last)) = (data.first(), data.last()) {
+            self.position = self
+                .position
+                .max(first.into_inner() as f64)
+                .min(last.into_inner() as f64 + 1.0);
+        }
+    }
+
+    /// Advances the playback by the time passed since the last update, unless it's paused, and
+    /// returns the new position.
+    pub fn update(&mut self, data: &Store) -> f64 {
+        if !self.paused {
+            self.position +=
+                self.updated.elapsed().as_secs_f64() * self.speed / self.seconds_per_division;
+        }
+        self.updated = Instant::now();
+        self.clamp(data);
+
+        self.position
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Moves the playback by `offset` grid units.
+    pub fn seek(&mut self, offset: f64, data: &Store) {
+        self.position += offset;
+        self.clamp(data);
+    }
+
+    /// Shows one more sample, or one less if `forward` is false.
+    pub fn step(&mut self, forward: bool, data: &Store) {
+        let position = OrderedFloat(self.position as f32);
+
+        if forward {
+            // The first hidden sample is revealed by moving to the one after it.
+            let mut hidden = data
+                .range(position..OrderedFloat(f32::INFINITY))
+                .map(|(&time, _)| time);
+
+            if let Some(next) = hidden.next() {
+                self.position = match hidden.find(|&time| time > next) {
+                    Some(time) => time.into_inner() as f64,
+                    None => next.into_inner() as f64 + 1.0,
+                };
+            }
+        } else if let Some((&last, _)) = data
+            .range(OrderedFloat(f32::NEG_INFINITY)..position)
+            .next_back()
+        {
+            self.position = last.into_inner() as f64;
+        }
+
+        self.clamp(data);
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.max(MIN_SPEED).min(MAX_SPEED);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Describes the position in the recording and the playback speed.
+    pub fn summary(&self, data: &Store) -> String {
+        let (elapsed, duration) = match (data.first(), data.last()) {
+            (Some(first), Some(last)) => (
+                (self.position - first.into_inner() as f64).min((last - first).into_inner() as f64),
+                (last - first).into_inner() as f64,
+            ),
+            _ => (0.0, 0.0),
+        };
+
+        format!(
+            "Replay: {:.1} s of {:.1} s at {}×{}",
+            elapsed * self.seconds_per_division,
+            duration * self.seconds_per_division,
+            self.speed,
+            if self.paused { ", paused" } else { "" }
+        )
+    }
+}
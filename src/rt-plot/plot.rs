@@ -1,7 +1,8 @@
+use super::config::DataConfig;
 use super::renderer;
+use super::store::Store;
 use gl::types::{GLint, GLsizeiptr, GLuint, GLvoid};
 use ordered_float::OrderedFloat;
-use std::collections::BTreeMap;
 
 #[derive(Clone)]
 struct Vertex {
@@ -11,6 +12,8 @@ struct Vertex {
 }
 pub struct Mesh {
     vertices: Vec<Vertex>,
+    // Ranges of vertices drawn as separate lines, so that gaps in the data aren't bridged.
+    segments: Vec<std::ops::Range<usize>>,
     vao: GLuint,
     vbo: GLuint,
 }
@@ -99,7 +102,12 @@ pub fn generate_polynomial_graph(
     }
 
     let (vao, vbo) = generate_buffers(&vertices);
-    Mesh { vertices, vao, vbo }
+    Mesh {
+        segments: vec![0..vertices.len()],
+        vertices,
+        vao,
+        vbo,
+    }
 }
 
 pub fn generate_grid(time_divisions: u32, data_divisions: u32, color: renderer::Color) -> Mesh {
@@ -141,7 +149,12 @@ pub fn generate_grid(time_divisions: u32, data_divisions: u32, color: renderer::
 
     let (vao, vbo) = generate_buffers(&vertices);
 
-    Mesh { vertices, vao, vbo }
+    Mesh {
+        segments: vec![0..vertices.len()],
+        vertices,
+        vao,
+        vbo,
+    }
 }
 
 pub fn get_dimensions(
@@ -177,8 +190,79 @@ pub fn get_dimensions(
     )
 }
 
+/// A pane of the plot, with the indices of its channels.
+pub struct Pane {
+    pub channels: Vec<usize>,
+    pub divisions: u32,
+    pub zero_shift: f32,
+    pub label: String,
+}
+
+/// The panes in the data config. Channels that aren't in any of them, which are all of them if
+/// there are no panes, are drawn in one more pane at the bottom with the data grid.
+pub fn get_panes(data_config: &DataConfig) -> Vec<Pane> {
+    let mut panes: Vec<Pane> = data_config
+        .grid
+        .panes
+        .iter()
+        .map(|pane| Pane {
+            channels: pane
+                .channels
+                .iter()
+                .map(|channel| data_config.channel_index(channel).unwrap())
+                .collect(),
+            divisions: pane.divisions,
+            zero_shift: pane.zero_shift,
+            label: pane.label.clone(),
+        })
+        .collect();
+
+    let rest: Vec<usize> = (0..data_config.y.len())
+        .filter(|i| !panes.iter().any(|pane| pane.channels.contains(i)))
+        .collect();
+    if !rest.is_empty() {
+        panes.push(Pane {
+            channels: rest,
+            divisions: data_config.grid.data.divisions,
+            zero_shift: data_config.grid.data.zero_shift,
+            label: data_config.grid.data.label.clone(),
+        });
+    }
+
+    panes
+}
+
+/// Splits the grid of all the divisions of the panes into one viewport for each of them, from the
+/// top to the bottom.
+pub fn get_pane_dimensions(
+    width: u32,
+    height: u32,
+    padding: u32,
+    time_divisions: u32,
+    panes: &[Pane],
+) -> Vec<(i32, i32, i32, i32)> {
+    let divisions: u32 = panes.iter().map(|pane| pane.divisions).sum();
+    let (x, y, grid_width, grid_height) =
+        get_dimensions(width, height, padding, time_divisions, divisions);
+
+    // Edges are rounded the same way for neighbouring panes, so that they don't overlap.
+    let edge = |divisions_below: u32| y + grid_height * divisions_below as i32 / divisions as i32;
+
+    let mut divisions_below = divisions;
+    panes
+        .iter()
+        .map(|pane| {
+            let top = edge(divisions_below);
+            divisions_below -= pane.divisions;
+            let bottom = edge(divisions_below);
+
+            (x, bottom, grid_width, top - bottom)
+        })
+        .collect()
+}
+
 pub fn generate_graphs(
-    data: &BTreeMap<OrderedFloat<f32>, Vec<f32>>,
+    data: &Store,
     range: &std::ops::Range<OrderedFloat<f32>>,
     channels: usize,
     colors: &Vec<renderer::Color>,
@@ -186,9 +270,18 @@ pub fn generate_graphs(
     focused_channel: &Option<usize>,
 ) -> Vec<Mesh> {
     let mut vertices: Vec<Vec<Vertex>> = vec![vec![]; channels];
+    let mut segments: Vec<Vec<std::ops::Range<usize>>> = vec![vec![0..0]; channels];
 
     for (time, data) in data.range(range.to_owned()) {
         for (i, y) in data.iter().enumerate() {
+            // Missing values end the current segment.
+            if y.is_nan() {
+                if !segments[i].last().unwrap().is_empty() {
+                    segments[i].push(vertices[i].len()..vertices[i].len());
+                }
+                continue;
+            }
+
             let mut focused = true;
 
             if let (Some(subrange), Some(j)) = (&subrange, focused_channel) {
@@ -212,6 +305,7 @@ pub fn generate_graphs(
                     true => colors[i],
                 },
             });
+            segments[i].last_mut().unwrap().end += 1;
         }
     }
 
@@ -220,6 +314,7 @@ pub fn generate_graphs(
         let (vao, vbo) = generate_buffers(&vertices[i]);
         result.push(Mesh {
             vertices: vertices[i].clone(),
+            segments: segments[i].clone(),
             vao,
             vbo,
         })
@@ -228,19 +323,98 @@ pub fn generate_graphs(
     result
 }
 
-pub fn draw_grid(
-    grid: &Mesh,
-    width: u32,
-    height: u32,
-    padding: u32,
-    time_divisions: u32,
-    data_divisions: u32,
-) {
-    let (x, y, width, height) =
-        get_dimensions(width, height, padding, time_divisions, data_divisions);
+/// Plots one channel against another over the time range. Older points are fainter, so that the
+/// direction of the curve can be seen.
+pub fn generate_xy_graph(
+    data: &Store,
+    range: &std::ops::Range<OrderedFloat<f32>>,
+    x_channel: usize,
+    y_channel: usize,
+    color: renderer::Color,
+) -> Mesh {
+    let mut vertices = vec![];
+    let mut segments = vec![0..0];
+    let duration = (range.end - range.start).into_inner();
 
+    for (time, data) in data.range(range.to_owned()) {
+        let (x, y) = (data[x_channel], data[y_channel]);
+
+        // Missing values end the current segment.
+        if x.is_nan() || y.is_nan() {
+            if !segments.last().unwrap().is_empty() {
+                segments.push(vertices.len()..vertices.len());
+            }
+            continue;
+        }
+
+        let age = (range.end - *time).into_inner() / duration;
+        vertices.push(Vertex {
+            x,
+            y,
+            color: renderer::Color {
+                a: color.a * (1.0 - 0.8 * age),
+                ..color
+            },
+        });
+        segments.last_mut().unwrap().end += 1;
+    }
+
+    let (vao, vbo) = generate_buffers(&vertices);
+    Mesh {
+        vertices,
+        segments,
+        vao,
+        vbo,
+    }
+}
+
+/// A line through points given in OpenGL coordinates of the grid, like a spectrum.
+pub fn generate_line(points: &[(f32, f32)], color: renderer::Color) -> Mesh {
+    let vertices: Vec<Vertex> = points
+        .iter()
+        .map(|&(x, y)| Vertex { x, y, color })
+        .collect();
+
+    let (vao, vbo) = generate_buffers(&vertices);
+    Mesh {
+        segments: vec![0..vertices.len()],
+        vertices,
+        vao,
+        vbo,
+    }
+}
+
+/// Vertical lines from the bottom of the grid up to each of the points, like markers of peaks.
+pub fn generate_markers(points: &[(f32, f32)], color: renderer::Color) -> Mesh {
+    let mut vertices = vec![];
+    let mut segments = vec![];
+
+    for &(x, y) in points {
+        segments.push(vertices.len()..vertices.len() + 2);
+        vertices.push(Vertex { x, y: -1.0, color });
+        vertices.push(Vertex { x, y, color });
+    }
+
+    let (vao, vbo) = generate_buffers(&vertices);
+    Mesh {
+        vertices,
+        segments,
+        vao,
+        vbo,
+    }
+}
+
+/// Makes the area given by `get_dimensions` the one drawn into.
+pub fn set_viewport((x, y, width, height): (i32, i32, i32, i32)) {
     unsafe {
         gl::Viewport(x, y, width, height);
+    }
+}
+
+pub fn draw_grid(grid: &Mesh, dimensions: (i32, i32, i32, i32)) {
+    set_viewport(dimensions);
+
+    unsafe {
         gl::BindVertexArray(grid.vao);
         gl::LineWidth(1.0);
         gl::DrawArrays(gl::LINES, 0, grid.vertices.len() as i32);
@@ -251,6 +425,8 @@ pub fn draw_graph(graph: &Mesh) {
     unsafe {
         gl::BindVertexArray(graph.vao);
         gl::LineWidth(3.0);
-        gl::DrawArrays(gl::LINE_STRIP, 0, graph.vertices.len() as i32);
+        for segment in &graph.segments {
+            gl::DrawArrays(gl::LINE_STRIP, segment.start as i32, segment.len() as i32);
+        }
     }
 }
@@ -204,6 +204,18 @@ pub fn initialize_text_shaders() -> Program {
     Program::from_shaders(&[vert_shader, frag_shader]).unwrap()
 }
 
+pub fn initialize_image_shaders() -> Program {
+    let vert_shader =
+        Shader::from_vert_source(&CString::new(include_str!("shaders/image.vert")).unwrap())
+            .unwrap();
+
+    let frag_shader =
+        Shader::from_frag_source(&CString::new(include_str!("shaders/image.frag")).unwrap())
+            .unwrap();
+
+    Program::from_shaders(&[vert_shader, frag_shader]).unwrap()
+}
+
 pub fn initialize_window(
     width: u32,
     height: u32,
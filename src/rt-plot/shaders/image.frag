@@ -0,0 +1,11 @@
+#version 330 core
+
+out vec4 Color;
+
+in vec2 texCoord; 
+
+uniform sampler2D imageTexture;
+
+void main() {
+    Color = texture(imageTexture, texCoord);
+}
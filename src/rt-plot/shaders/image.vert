@@ -0,0 +1,17 @@
+#version 330 core
+
+layout (location = 0) in vec2 Position;
+layout (location = 1) in vec2 TextureCoordinates;
+
+out vec2 texCoord;
+
+uniform mat3 coordinate_transform;
+uniform vec2 translation;
+
+// Only the horizontal position is in grid coordinates, so that images share the time axis with the
+// graphs while always spanning the height of the grid.
+void main() {
+    vec3 position = coordinate_transform * vec3(Position + translation, 1.0);
+    gl_Position = vec4(position.x, Position.y, 0.0, 1.0);
+    texCoord = TextureCoordinates;
+}
@@ -0,0 +1,431 @@
+use super::binary;
+use super::config::{DataConfig, Endianness, Format};
+use super::input::ParseError;
+use super::status::Status;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What a data source produced when asked for more input.
+pub enum Chunk {
+    /// Part of a stream of bytes, which may end in the middle of a line.
+    Stream(Vec<u8>),
+    /// A self-contained message, like a datagram, which never ends in the middle of a record.
+    Message(Vec<u8>),
+    /// Nothing arrived in time.
+    Idle,
+    /// The connection was lost, so whatever was received of the last record is incomplete.
+    Disconnected,
+    /// There will be no more input.
+    End,
+}
+
+/// Anything rt-plot can read data from. Sources only deliver raw bytes, and `ingest::SampleSource`
+/// turns them into samples, so that a new source only has to deal with its transport.
+pub trait DataSource: Send {
+    /// Waits at most `timeout` for more input, so that the input thread can be stopped even if the
+    /// source is blocked.
+    fn read(&mut self, timeout: Duration) -> io::Result<Chunk>;
+}
+
+/// Reads from anything that implements `Read` on a separate thread, so that waiting for input
+/// can be interrupted.
+///
+/// The thread stops at its next read once the source is dropped. A read that blocks, like one of
+/// stdin, can't be interrupted portably though, so in that case the thread is left behind and only
+/// ends with the program. Nothing waits for it, so it doesn't keep the program from exiting.
+pub struct ReaderSource {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+}
+
+impl ReaderSource {
+    pub fn new<R: Read + Send + 'static>(mut reader: R) -> ReaderSource {
+        let (sender, receiver) = mpsc::sync_channel(16);
+
+        // The thread ends once the input ends, or once it has read something after the source was
+        // dropped.
+        thread::spawn(move || loop {
+            let mut buffer = vec![0; 1 << 16];
+
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buffer.truncate(n);
+                    if sender.send(Ok(buffer)).is_err() {
+                        break;
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => {
+                    sender.send(Err(error)).ok();
+                    break;
+                }
+            }
+        });
+
+        ReaderSource { receiver }
+    }
+}
+
+impl DataSource for ReaderSource {
+    fn read(&mut self, timeout: Duration) -> io::Result<Chunk> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(Ok(bytes)) => Ok(Chunk::Stream(bytes)),
+            Ok(Err(error)) => Err(error),
+            Err(RecvTimeoutError::Timeout) => Ok(Chunk::Idle),
+            Err(RecvTimeoutError::Disconnected) => Ok(Chunk::End),
+        }
+    }
+}
+
+// Sequence numbers that jump back by more than this mean the sender was restarted.
+const SEQUENCE_RESTART_THRESHOLD: u64 = 1000;
+
+/// Receives datagrams on a UDP socket. Each datagram holds one or more samples in the configured
+/// format, optionally preceded by a sequence number used to detect dropped and reordered packets.
+pub struct UdpSource {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+    timeout: Option<Duration>,
+    format: Format,
+    // Of the sequence numbers of binary datagrams, which follow the layout of the records.
+    endianness: Endianness,
+    sequence_numbers: bool,
+    next_sequence: Option<u64>,
+    status: Arc<Status>,
+}
+
+impl UdpSource {
+    pub fn new(
+        address: &str,
+        data_config: &DataConfig,
+        status: Arc<Status>,
+    ) -> io::Result<UdpSource> {
+        Ok(UdpSource {
+            socket: UdpSocket::bind(address)?,
+            buffer: vec![0; 1 << 16],
+            timeout: None,
+            format: data_config.input.format,
+            endianness: data_config.input.layout.endianness,
+            sequence_numbers: data_config.input.sequence_numbers,
+            next_sequence: None,
+            status,
+        })
+    }
+
+    // The sequence number is a 32-bit integer with the endianness of the layout in binary
+    // datagrams, and the first line of text ones. Returns the sequence number and the rest of the
+    // datagram.
+    fn split_sequence_number<'a>(&self, datagram: &'a [u8]) -> Option<(u64, &'a [u8])> {
+        match self.format {
+            Format::Binary if datagram.len() >= 4 => Some((
+                binary::read_unsigned(&datagram[..4], self.endianness),
+                &datagram[4..],
+            )),
+            Format::Binary => None,
+            _ => {
+                let end = datagram
+                    .iter()
+                    .position(|&byte| byte == b'\n')
+                    .unwrap_or(datagram.len());
+                let sequence = std::str::from_utf8(&datagram[..end])
+                    .ok()?
+                    .trim()
+                    .parse::<u64>()
+                    .ok()?;
+
+                Some((sequence, &datagram[(end + 1).min(datagram.len())..]))
+            }
+        }
+    }
+
+    fn track_sequence_number(&mut self, sequence: u64) {
+        match self.next_sequence {
+            Some(expected) if sequence + SEQUENCE_RESTART_THRESHOLD < expected => {
+                self.next_sequence = Some(sequence + 1);
+            }
+            Some(expected) if sequence < expected => {
+                // It arrived late, so it was counted as dropped when a later one arrived.
+                self.status.record_reordered_packet();
+            }
+            Some(expected) => {
+                self.status
+                    .record_dropped_packets((sequence - expected) as usize);
+                self.next_sequence = Some(sequence + 1);
+            }
+            None => {
+                self.next_sequence = Some(sequence + 1);
+            }
+        }
+    }
+}
+
+impl DataSource for UdpSource {
+    fn read(&mut self, timeout: Duration) -> io::Result<Chunk> {
+        if self.timeout != Some(timeout) {
+            self.socket.set_read_timeout(Some(timeout))?;
+            self.timeout = Some(timeout);
+        }
+
+        let length = match self.socket.recv(&mut self.buffer) {
+            Ok(length) => length,
+            Err(error)
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Ok(Chunk::Idle);
+            }
+            Err(error) => return Err(error),
+        };
+
+        if !self.sequence_numbers {
+            return Ok(Chunk::Message(self.buffer[..length].to_vec()));
+        }
+
+        match self.split_sequence_number(&self.buffer[..length]) {
+            Some((sequence, payload)) => {
+                let payload = payload.to_vec();
+                self.track_sequence_number(sequence);
+                Ok(Chunk::Message(payload))
+            }
+            None => {
+                self.status.record_parse_error(ParseError::InvalidNumber);
+                Ok(Chunk::Idle)
+            }
+        }
+    }
+}
+
+// How long to wait before trying to connect again after a failed attempt.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+// How long an attempt to connect to a server can take. It's usually longer than a read, so it's
+// made on a thread of its own.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let address: SocketAddr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid address."))?;
+    TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+}
+
+/// Reads newline-delimited samples from a TCP connection, either by connecting to a server or by
+/// accepting a connection. Lost connections are reestablished automatically.
+pub struct TcpSource {
+    address: String,
+    listener: Option<TcpListener>,
+    stream: Option<TcpStream>,
+    // The result of the attempt to connect that is in progress, if any.
+    connecting: Option<Receiver<io::Result<TcpStream>>>,
+    last_attempt: Option<Instant>,
+    buffer: Vec<u8>,
+    status: Arc<Status>,
+}
+
+impl TcpSource {
+    pub fn connect(address: &str, status: Arc<Status>) -> TcpSource {
+        status.set_source_state(Some(format!("Connecting to {}", address)));
+
+        TcpSource {
+            address: String::from(address),
+            listener: None,
+            stream: None,
+            connecting: None,
+            last_attempt: None,
+            buffer: vec![0; 1 << 16],
+            status,
+        }
+    }
+
+    pub fn listen(address: &str, status: Arc<Status>) -> io::Result<TcpSource> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        status.set_source_state(Some(format!("Waiting for a connection on {}", address)));
+
+        Ok(TcpSource {
+            address: String::from(address),
+            listener: Some(listener),
+            stream: None,
+            connecting: None,
+            last_attempt: None,
+            buffer: vec![0; 1 << 16],
+            status,
+        })
+    }
+
+    // Accepts a pending connection, or waits up to the timeout for the attempt to connect to the
+    // server, starting it if there isn't one. Returns `None` while the attempt is in progress.
+    fn try_connect(&mut self, timeout: Duration) -> io::Result<Option<TcpStream>> {
+        if let Some(listener) = &self.listener {
+            let (stream, _) = listener.accept()?;
+            stream.set_nonblocking(false)?;
+            return Ok(Some(stream));
+        }
+
+        let address = self.address.clone();
+        let connecting = self.connecting.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                let _ = sender.send(connect(&address));
+            });
+            receiver
+        });
+
+        match connecting.recv_timeout(timeout) {
+            Ok(result) => {
+                self.connecting = None;
+                result.map(Some)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => {
+                self.connecting = None;
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The attempt to connect failed.",
+                ))
+            }
+        }
+    }
+
+    fn disconnected_state(&self) -> String {
+        match self.listener {
+            Some(_) => format!("Disconnected, waiting for a connection on {}", self.address),
+            None => format!("Disconnected, reconnecting to {}", self.address),
+        }
+    }
+}
+
+impl DataSource for TcpSource {
+    fn read(&mut self, timeout: Duration) -> io::Result<Chunk> {
+        if self.stream.is_none() {
+            // Don't keep hammering a server that refuses connections.
+            if let Some(last_attempt) = self.last_attempt {
+                let elapsed = last_attempt.elapsed();
+                if elapsed < RECONNECT_INTERVAL {
+                    thread::sleep(timeout.min(RECONNECT_INTERVAL.saturating_sub(elapsed)));
+                    return Ok(Chunk::Idle);
+                }
+            }
+
+            match self.try_connect(timeout) {
+                Ok(Some(stream)) => {
+                    stream.set_read_timeout(Some(timeout))?;
+                    self.stream = Some(stream);
+                    self.last_attempt = None;
+                    self.status.set_source_state(None);
+                }
+                Ok(None) => return Ok(Chunk::Idle),
+                Err(_) => {
+                    self.last_attempt = Some(Instant::now());
+                    return Ok(Chunk::Idle);
+                }
+            }
+        }
+
+        let stream = self.stream.as_mut().unwrap();
+
+        match stream.read(&mut self.buffer) {
+            Ok(0) => {}
+            Ok(length) => return Ok(Chunk::Stream(self.buffer[..length].to_vec())),
+            Err(error)
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut
+                    || error.kind() == io::ErrorKind::Interrupted =>
+            {
+                return Ok(Chunk::Idle);
+            }
+            Err(_) => {}
+        }
+
+        // The connection was either closed or broken.
+        self.stream = None;
+        self.last_attempt = Some(Instant::now());
+        self.status
+            .set_source_state(Some(self.disconnected_state()));
+
+        Ok(Chunk::Disconnected)
+    }
+}
+
+/// Opens the source described by `source`, which is one of "stdin", "file:PATH", "udp:HOST:PORT",
+/// "tcp:HOST:PORT" or "tcp-listen:HOST:PORT".
+pub fn open(
+    source: &str,
+    data_config: &DataConfig,
+    status: Arc<Status>,
+) -> io::Result<Box<dyn DataSource>> {
+    let (kind, argument) = match source.find(':') {
+        Some(i) => (&source[..i], &source[i + 1..]),
+        None => (source, ""),
+    };
+
+    match kind {
+        "stdin" => Ok(Box::new(ReaderSource::new(io::stdin()))),
+        "file" => Ok(Box::new(ReaderSource::new(File::open(argument)?))),
+        "udp" => Ok(Box::new(UdpSource::new(argument, data_config, status)?)),
+        "tcp" => Ok(Box::new(TcpSource::connect(argument, status))),
+        "tcp-listen" => Ok(Box::new(TcpSource::listen(argument, status)?)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown data source \"{}\".", source),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // Reads until the source produces something other than `Idle`.
+    fn next_chunk(source: &mut TcpSource) -> Chunk {
+        let started = Instant::now();
+
+        while started.elapsed() < Duration::from_secs(10) {
+            match source.read(Duration::from_millis(50)).unwrap() {
+                Chunk::Idle => {}
+                chunk => return chunk,
+            }
+        }
+
+        panic!("The source didn't produce anything.");
+    }
+
+    #[test]
+    fn tcp_source_reads_and_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // Every connection sends a line and is closed right away.
+        let server = thread::spawn(move || {
+            for line in [&b"1 2\n"[..], &b"3 4\n"[..]].iter() {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.write_all(line).unwrap();
+            }
+        });
+
+        let mut source = TcpSource::connect(&address, Arc::new(Status::new()));
+
+        match next_chunk(&mut source) {
+            Chunk::Stream(bytes) => assert_eq!(bytes, b"1 2\n"),
+            _ => panic!("Expected the first line."),
+        }
+        match next_chunk(&mut source) {
+            Chunk::Disconnected => {}
+            _ => panic!("Expected the connection to be lost."),
+        }
+        match next_chunk(&mut source) {
+            Chunk::Stream(bytes) => assert_eq!(bytes, b"3 4\n"),
+            _ => panic!("Expected the line from the new connection."),
+        }
+
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+use super::config::{DataConfig, SpectrumScale, Window};
+use super::store::Store;
+use ordered_float::OrderedFloat;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// The number of points of the FFT is the number of samples rounded up to a power of two, within
+// these limits.
+const MIN_POINTS: usize = 16;
+const MAX_POINTS: usize = 65536;
+
+const DB_PER_DIVISION: f32 = 20.0;
+
+fn window_weights(window: Window, points: usize) -> Vec<f32> {
+    (0..points)
+        .map(|i| {
+            let x = 2.0 * PI * i as f32 / (points - 1) as f32;
+
+            match window {
+                Window::Rectangular => 1.0,
+                Window::Hann => 0.5 - 0.5 * x.cos(),
+                Window::Hamming => 0.54 - 0.46 * x.cos(),
+                Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+                Window::FlatTop => {
+                    0.21557895 - 0.41663158 * x.cos() + 0.277263158 * (2.0 * x).cos()
+                        - 0.083578947 * (3.0 * x).cos()
+                        + 0.006947368 * (4.0 * x).cos()
+                }
+            }
+        })
+        .collect()
+}
+
+// Formats a value with three significant digits.
+fn significant(value: f32) -> String {
+    let decimals = (2.0 - value.abs().log10().floor()).max(0.0);
+    let decimals = if decimals.is_finite() { decimals } else { 0.0 };
+
+    format!("{:.*}", decimals as usize, value)
+}
+
+pub fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-12).log10()
+}
+
+/// Computes the amplitudes of samples, given as times in grid units and values, with an FFT of
+/// `points` points, for frequencies from zero up to half the sample rate, and returns them with the
+/// spacing of the frequencies in Hz. The samples are resampled evenly first, since their times may
+/// not be. Returns `None` if there are too few samples.
+pub fn analyze(
+    planner: &mut FftPlanner<f32>,
+    samples: &[(f32, f32)],
+    points: usize,
+    window: Window,
+    seconds_per_grid_unit: f32,
+) -> Option<(Vec<f32>, f32)> {
+    if samples.len() < 4 {
+        return None;
+    }
+
+    let (start, end) = (samples[0].0, samples[samples.len() - 1].0);
+    let step = (end - start) / (points - 1) as f32;
+    if !(step > 0.0) {
+        return None;
+    }
+
+    // Linear interpolation between the samples around every point.
+    let mut k = 0;
+    let values: Vec<f32> = (0..points)
+        .map(|i| {
+            let time = start + i as f32 * step;
+            while k + 2 < samples.len() && samples[k + 1].0 <= time {
+                k += 1;
+            }
+
+            let ((t0, v0), (t1, v1)) = (samples[k], samples[k + 1]);
+            match t1 > t0 {
+                true => v0 + (v1 - v0) * ((time - t0) / (t1 - t0)).max(0.0).min(1.0),
+                false => v1,
+            }
+        })
+        .collect();
+
+    // The mean is removed before the window is applied, so that it doesn't leak into low
+    // frequencies, and is the amplitude at zero.
+    let mean = values.iter().sum::<f32>() / points as f32;
+    let weights = window_weights(window, points);
+    let mut buffer: Vec<Complex<f32>> = values
+        .iter()
+        .zip(&weights)
+        .map(|(value, weight)| Complex::new((value - mean) * weight, 0.0))
+        .collect();
+
+    planner.plan_fft_forward(points).process(&mut buffer);
+
+    // Scaled so that a sine shows its amplitude, which is split between two frequencies.
+    let gain: f32 = weights.iter().sum();
+    let amplitudes = buffer[..=points / 2]
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match i {
+            0 => mean.abs(),
+            _ => 2.0 * value.norm() / gain,
+        })
+        .collect();
+
+    Some((
+        amplitudes,
+        1.0 / (points as f32 * step * seconds_per_grid_unit),
+    ))
+}
+
+/// Amplitudes of a channel in its calibrated units, for frequencies from zero up to half the sample
+/// rate.
+pub struct Magnitudes {
+    pub amplitudes: Vec<f32>,
+    // Spacing of the frequencies in Hz.
+    pub resolution: f32,
+    // Level of the top of the grid in dB, the highest amplitude rounded up to a division.
+    top: f32,
+}
+
+impl Magnitudes {
+    pub fn max_frequency(&self) -> f32 {
+        (self.amplitudes.len() - 1) as f32 * self.resolution
+    }
+}
+
+/// Computes the magnitude spectrum of a channel, averaged over the last few frames, and finds its
+/// peaks.
+pub struct Spectrum {
+    window: Window,
+    scale: SpectrumScale,
+    averages: usize,
+    peaks: usize,
+    channel: usize,
+    planner: FftPlanner<f32>,
+    // Amplitudes of the most recent spectra, which all have the same number of points.
+    history: VecDeque<Vec<f32>>,
+}
+
+impl Spectrum {
+    pub fn new(data_config: &DataConfig) -> Spectrum {
+        let spectrum = &data_config.spectrum;
+
+        Spectrum {
+            window: spectrum.window,
+            scale: spectrum.scale,
+            averages: spectrum.averages,
+            peaks: spectrum.peaks,
+            channel: 0,
+            planner: FftPlanner::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Forgets the spectra that are averaged.
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    pub fn next_window(&mut self) {
+        self.window = match self.window {
+            Window::Rectangular => Window::Hann,
+            Window::Hann => Window::Hamming,
+            Window::Hamming => Window::Blackman,
+            Window::Blackman => Window::FlatTop,
+            Window::FlatTop => Window::Rectangular,
+        };
+        self.reset();
+    }
+
+    pub fn toggle_scale(&mut self) {
+        self.scale = match self.scale {
+            SpectrumScale::Linear => SpectrumScale::Db,
+            SpectrumScale::Db => SpectrumScale::Linear,
+        };
+    }
+
+    /// Computes the spectrum of a channel over the time range, or returns `None` if there are too
+    /// few samples.
+    pub fn compute(
+        &mut self,
+        data: &Store,
+        data_config: &DataConfig,
+        range: &std::ops::Range<OrderedFloat<f32>>,
+        channel: usize,
+    ) -> Option<Magnitudes> {
+        if channel != self.channel {
+            self.channel = channel;
+            self.reset();
+        }
+
+        let y = &data_config.y[channel];
+        let samples: Vec<(f32, f32)> = data
+            .range(range.clone())
+            .map(|(time, values)| {
+                (
+                    time.into_inner(),
+                    values[channel] * y.raw_per_division + y.raw_offset,
+                )
+            })
+            .filter(|(_, value)| !value.is_nan())
+            .collect();
+
+        let points = samples
+            .len()
+            .next_power_of_two()
+            .max(MIN_POINTS)
+            .min(MAX_POINTS);
+        let (amplitudes, resolution) = analyze(
+            &mut self.planner,
+            &samples,
+            points,
+            self.window,
+            data_config.grid.time.seconds_per_division,
+        )?;
+
+        if self
+            .history
+            .front()
+            .map_or(false, |previous| previous.len() != amplitudes.len())
+        {
+            self.reset();
+        }
+        self.history.push_back(amplitudes);
+        while self.history.len() > self.averages {
+            self.history.pop_front();
+        }
+
+        let mut amplitudes = vec![0.0; points / 2 + 1];
+        for spectrum in &self.history {
+            for (sum, amplitude) in amplitudes.iter_mut().zip(spectrum) {
+                *sum += amplitude / self.history.len() as f32;
+            }
+        }
+
+        let highest = amplitudes.iter().cloned().fold(0.0, f32::max);
+
+        Some(Magnitudes {
+            amplitudes,
+            resolution,
+            top: (to_db(highest) / DB_PER_DIVISION).ceil() * DB_PER_DIVISION,
+        })
+    }
+
+    /// Frequencies and amplitudes of the highest peaks, highest first. The frequencies are
+    /// interpolated between the points of the spectrum.
+    pub fn peaks(&self, magnitudes: &Magnitudes) -> Vec<(f32, f32)> {
+        let a = &magnitudes.amplitudes;
+
+        let mut peaks: Vec<(f32, f32)> = (1..a.len().saturating_sub(1))
+            .filter(|&i| a[i] > a[i - 1] && a[i] >= a[i + 1])
+            .map(|i| {
+                let (left, center, right) = (to_db(a[i - 1]), to_db(a[i]), to_db(a[i + 1]));
+                let curvature = left - 2.0 * center + right;
+                let offset = match curvature < 0.0 {
+                    true => 0.5 * (left - right) / curvature,
+                    false => 0.0,
+                };
+
+                ((i as f32 + offset) * magnitudes.resolution, a[i])
+            })
+            .collect();
+
+        peaks.sort_by(|p, q| q.1.partial_cmp(&p.1).unwrap());
+        peaks.truncate(self.peaks);
+        peaks
+    }
+
+    /// Position of an amplitude at a frequency on the grid, from -1 to 1 on both axes. The bottom
+    /// of the grid is zero on the linear scale.
+    pub fn position(
+        &self,
+        magnitudes: &Magnitudes,
+        data_config: &DataConfig,
+        frequency: f32,
+        amplitude: f32,
+    ) -> (f32, f32) {
+        let divisions = data_config.grid.data.divisions as f32;
+
+        let y = match self.scale {
+            SpectrumScale::Linear => {
+                -1.0 + 2.0 * amplitude / (data_config.y[self.channel].raw_per_division * divisions)
+            }
+            SpectrumScale::Db => {
+                1.0 - 2.0 * (magnitudes.top - to_db(amplitude)) / (DB_PER_DIVISION * divisions)
+            }
+        };
+
+        (-1.0 + 2.0 * frequency / magnitudes.max_frequency(), y)
+    }
+
+    pub fn points(&self, magnitudes: &Magnitudes, data_config: &DataConfig) -> Vec<(f32, f32)> {
+        magnitudes
+            .amplitudes
+            .iter()
+            .enumerate()
+            .map(|(i, &amplitude)| {
+                let frequency = i as f32 * magnitudes.resolution;
+                self.position(magnitudes, data_config, frequency, amplitude)
+            })
+            .collect()
+    }
+
+    fn level(&self, amplitude: f32, data_config: &DataConfig) -> String {
+        let unit = data_config.y[self.channel].unit.as_deref().unwrap_or("");
+
+        match self.scale {
+            SpectrumScale::Linear => format!("{} {}", significant(amplitude), unit)
+                .trim_end()
+                .to_string(),
+            SpectrumScale::Db => format!("{:.1} dB", to_db(amplitude)),
+        }
+    }
+
+    /// Describes the frequency axis and the window.
+    pub fn frequency_label(&self, magnitudes: &Magnitudes, data_config: &DataConfig) -> String {
+        let window = match self.window {
+            Window::Rectangular => "rectangular",
+            Window::Hann => "Hann",
+            Window::Hamming => "Hamming",
+            Window::Blackman => "Blackman",
+            Window::FlatTop => "flat top",
+        };
+
+        format!(
+            "{} Hz/div, {} window",
+            significant(magnitudes.max_frequency() / data_config.grid.time.divisions as f32),
+            window
+        )
+    }
+
+    /// Describes the amplitude axis. Levels in dB are relative to one unit of the channel.
+    pub fn amplitude_label(&self, magnitudes: &Magnitudes, data_config: &DataConfig) -> String {
+        let y = &data_config.y[self.channel];
+        let unit = y.unit.as_deref().unwrap_or("");
+
+        let scale = match self.scale {
+            SpectrumScale::Linear => format!("{} {}/div", y.raw_per_division, unit),
+            SpectrumScale::Db => match unit {
+                "" => format!("{} dB/div, top {} dB", DB_PER_DIVISION, magnitudes.top),
+                unit => format!(
+                    "{} dB/div re 1 {}, top {} dB",
+                    DB_PER_DIVISION, unit, magnitudes.top
+                ),
+            },
+        };
+
+        match self.averages {
+            1 => scale,
+            averages => format!("{}, {} averages", scale, averages),
+        }
+    }
+
+    pub fn peaks_label(&self, peaks: &[(f32, f32)], data_config: &DataConfig) -> String {
+        peaks
+            .iter()
+            .map(|&(frequency, amplitude)| {
+                format!(
+                    "{} Hz: {}",
+                    significant(frequency),
+                    self.level(amplitude, data_config)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("   ")
+    }
+}
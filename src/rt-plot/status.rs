@@ -0,0 +1,98 @@
+use super::input::ParseError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Counters shared between the input thread and the render loop that end up in the status area.
+pub struct Status {
+    parse_errors: Vec<AtomicUsize>,
+    resets: AtomicUsize,
+    dropped_packets: AtomicUsize,
+    reordered_packets: AtomicUsize,
+    // Set when the data source ends or fails. It changes rarely, so a lock is fine here.
+    source_state: Mutex<Option<String>>,
+}
+
+impl Status {
+    pub fn new() -> Status {
+        Status {
+            parse_errors: ParseError::ALL
+                .iter()
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+            resets: AtomicUsize::new(0),
+            dropped_packets: AtomicUsize::new(0),
+            reordered_packets: AtomicUsize::new(0),
+            source_state: Mutex::new(None),
+        }
+    }
+
+    pub fn record_parse_error(&self, error: ParseError) {
+        self.parse_errors[error as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reset(&self) {
+        self.resets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_packets(&self, count: usize) {
+        self.dropped_packets.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// A reordered packet was counted as dropped when a later one arrived first.
+    pub fn record_reordered_packet(&self) {
+        self.reordered_packets.fetch_add(1, Ordering::Relaxed);
+        self.dropped_packets
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |dropped| {
+                Some(dropped.saturating_sub(1))
+            })
+            .ok();
+    }
+
+    pub fn set_source_state(&self, state: Option<String>) {
+        *self.source_state.lock().unwrap() = state;
+    }
+
+    /// Returns a single line describing everything worth reporting, or `None` if there is nothing
+    /// to report.
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = vec![];
+
+        if let Some(state) = &*self.source_state.lock().unwrap() {
+            parts.push(state.clone());
+        }
+
+        let parse_errors: Vec<String> = ParseError::ALL
+            .iter()
+            .filter_map(
+                |&error| match self.parse_errors[error as usize].load(Ordering::Relaxed) {
+                    0 => None,
+                    count => Some(format!("{} {}", count, error)),
+                },
+            )
+            .collect();
+
+        if !parse_errors.is_empty() {
+            parts.push(format!("Skipped input: {}", parse_errors.join(", ")));
+        }
+
+        match self.resets.load(Ordering::Relaxed) {
+            0 => {}
+            resets => parts.push(format!("Device resets: {}", resets)),
+        }
+
+        let dropped = self.dropped_packets.load(Ordering::Relaxed);
+        let reordered = self.reordered_packets.load(Ordering::Relaxed);
+        if dropped > 0 || reordered > 0 {
+            parts.push(format!(
+                "Packets: {} dropped, {} reordered",
+                dropped, reordered
+            ));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("   "))
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use super::config::{DataConfig, Duplicates, OutOfOrder};
+use super::ingest::Sample;
+use ordered_float::OrderedFloat;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+
+// Samples are keyed by their time and a sequence number, so that several samples with the same
+// timestamp can be kept if the duplicates policy says so.
+type Key = (OrderedFloat<f32>, u32);
+
+/// All the samples received so far, ordered by time, with the oldest ones evicted according to the
+/// retention policy in the data config.
+pub struct Store {
+    samples: BTreeMap<Key, Vec<f32>>,
+    channels: usize,
+    seconds_per_division: f32,
+
+    duplicates: Duplicates,
+    out_of_order: OutOfOrder,
+    // Number of values averaged into every channel of each timestamp, for timestamps that had
+    // duplicates. Gaps in a channel aren't counted.
+    averaged: HashMap<OrderedFloat<f32>, Vec<u32>>,
+    duplicate_count: usize,
+    out_of_order_count: usize,
+
+    max_samples: Option<usize>,
+    // In grid units.
+    max_duration: Option<f32>,
+    max_bytes: Option<usize>,
+}
+
+impl Store {
+    pub fn new(data_config: &DataConfig) -> Store {
+        let retention = &data_config.retention;
+
+        Store {
+            samples: BTreeMap::new(),
+            channels: data_config.y.len() + data_config.y.iter().filter(|y| y.show_raw).count(),
+            seconds_per_division: data_config.grid.time.seconds_per_division,
+            duplicates: data_config.input.duplicates,
+            out_of_order: data_config.input.out_of_order,
+            averaged: HashMap::new(),
+            duplicate_count: 0,
+            out_of_order_count: 0,
+            max_samples: retention.max_samples,
+            max_duration: retention
+                .max_seconds
+                .map(|seconds| seconds / data_config.grid.time.seconds_per_division),
+            max_bytes: retention
+                .max_megabytes
+                .map(|megabytes| (megabytes * 1024.0 * 1024.0) as usize),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn first(&self) -> Option<OrderedFloat<f32>> {
+        self.samples.keys().next().map(|&(time, _)| time)
+    }
+
+    pub fn last(&self) -> Option<OrderedFloat<f32>> {
+        self.samples.keys().next_back().map(|&(time, _)| time)
+    }
+
+    pub fn range(
+        &self,
+        range: std::ops::Range<OrderedFloat<f32>>,
+    ) -> impl DoubleEndedIterator<Item = (&OrderedFloat<f32>, &Vec<f32>)> {
+        self.samples
+            .range((range.start, 0)..(range.end, 0))
+            .map(|((time, _), values)| (time, values))
+    }
+
+    /// Approximate number of bytes used by the samples, including the overhead of the tree.
+    pub fn memory(&self) -> usize {
+        let per_sample = mem::size_of::<Key>()
+            + mem::size_of::<Vec<f32>>()
+            + self.channels * mem::size_of::<f32>()
+            // Rough estimate of the allocator and tree node overhead.
+            + 2 * mem::size_of::<usize>();
+
+        self.samples.len() * per_sample
+    }
+
+    fn insert(&mut self, time: OrderedFloat<f32>, values: Vec<f32>) {
+        if let Some(last) = self.last() {
+            if time < last {
+                self.out_of_order_count += 1;
+
+                if let OutOfOrder::Reject = self.out_of_order {
+                    return;
+                }
+            }
+        }
+
+        // Retention may have evicted some of the samples with this timestamp, so the next sequence
+        // number follows the last one that is still stored.
+        let last_sequence = match self.samples.range((time, 0)..=(time, u32::MAX)).next_back() {
+            Some((&(_, sequence), _)) => sequence,
+            None => {
+                self.samples.insert((time, 0), values);
+                return;
+            }
+        };
+
+        self.duplicate_count += 1;
+
+        match self.duplicates {
+            Duplicates::KeepAll => {
+                self.samples.insert((time, last_sequence + 1), values);
+            }
+            Duplicates::KeepLast => {
+                self.samples.insert((time, 0), values);
+            }
+            Duplicates::Average => {
+                let mean = self.samples.get_mut(&(time, 0)).unwrap();
+                let counts = self.averaged.entry(time).or_insert_with(|| {
+                    mean.iter()
+                        .map(|value| if value.is_nan() { 0 } else { 1 })
+                        .collect()
+                });
+
+                // Update the running mean of every channel, skipping gaps.
+                for ((mean, count), value) in mean.iter_mut().zip(counts).zip(values) {
+                    if value.is_nan() {
+                        continue;
+                    }
+
+                    *count += 1;
+                    *mean = match *count {
+                        1 => value,
+                        count => *mean + (value - *mean) / count as f32,
+                    };
+                }
+            }
+            Duplicates::Reject => {}
+        }
+    }
+
+    pub fn extend(&mut self, samples: Vec<Sample>) {
+        for (time, values) in samples {
+            self.insert(time, values);
+        }
+
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while let (Some(&first), Some(last)) = (self.samples.keys().next(), self.last()) {
+            let too_many = self.max_samples.map_or(false, |max| self.len() > max);
+            let too_long = self
+                .max_duration
+                .map_or(false, |max| (last - first.0).into_inner() > max);
+            let too_big = self.max_bytes.map_or(false, |max| self.memory() > max);
+
+            if !(too_many || too_long || too_big) {
+                break;
+            }
+
+            self.samples.remove(&first);
+            self.averaged.remove(&first.0);
+        }
+    }
+
+    /// Describes how much history is kept, if there is a retention policy, and how many samples
+    /// had duplicate or out of order timestamps.
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = vec![];
+
+        if self.max_samples.is_some() || self.max_duration.is_some() || self.max_bytes.is_some() {
+            let duration = match (self.first(), self.last()) {
+                (Some(first), Some(last)) => {
+                    (last - first).into_inner() * self.seconds_per_division
+                }
+                _ => 0.0,
+            };
+
+            parts.push(format!(
+                "History: {} samples, {:.1} MB, {:.1} s",
+                self.len(),
+                self.memory() as f32 / 1024.0 / 1024.0,
+                duration
+            ));
+        }
+
+        if self.duplicate_count > 0 {
+            let action = match self.duplicates {
+                Duplicates::KeepAll => "kept",
+                Duplicates::KeepLast => "replaced",
+                Duplicates::Average => "averaged",
+                Duplicates::Reject => "rejected",
+            };
+
+            parts.push(format!(
+                "Duplicate timestamps: {} {}",
+                self.duplicate_count, action
+            ));
+        }
+
+        if self.out_of_order_count > 0 {
+            let action = match self.out_of_order {
+                OutOfOrder::Accept => "accepted",
+                OutOfOrder::Reject => "rejected",
+            };
+
+            parts.push(format!(
+                "Out of order: {} {}",
+                self.out_of_order_count, action
+            ));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("   "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(duplicates: &str, max_samples: Option<usize>) -> Store {
+        let retention = match max_samples {
+            Some(max_samples) => format!("[retention]\nmax_samples = {}\n", max_samples),
+            None => String::new(),
+        };
+        let data_config: DataConfig = toml::from_str(&format!(
+            r#"
+            {}
+            [input]
+            duplicates = "{}"
+
+            [grid]
+            label = ""
+            [grid.time]
+            divisions = 10
+            seconds_per_division = 1.0
+            raw_per_second = 1.0
+            label = ""
+            [grid.data]
+            divisions = 4
+            zero_shift = 0.0
+            label = ""
+
+            [[y]]
+            raw_offset = 0.0
+            raw_per_division = 1.0
+            [[y]]
+            raw_offset = 0.0
+            raw_per_division = 1.0
+            "#,
+            retention, duplicates
+        ))
+        .unwrap();
+
+        Store::new(&data_config)
+    }
+
+    fn values(store: &Store, time: f32) -> Vec<Vec<f32>> {
+        store
+            .range(OrderedFloat(time)..OrderedFloat(time + 1.0))
+            .map(|(_, values)| values.clone())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_all_duplicates_after_eviction() {
+        let mut store = store("keep_all", Some(2));
+
+        store.extend(vec![(OrderedFloat(1.0), vec![1.0, 1.0])]);
+        store.extend(vec![(OrderedFloat(1.0), vec![2.0, 2.0])]);
+        // Evicts the first sample, which had sequence number 0.
+        store.extend(vec![(OrderedFloat(1.0), vec![3.0, 3.0])]);
+        store.extend(vec![(OrderedFloat(1.0), vec![4.0, 4.0])]);
+
+        assert_eq!(values(&store, 1.0), vec![vec![3.0, 3.0], vec![4.0, 4.0]]);
+    }
+
+    #[test]
+    fn averages_around_gaps() {
+        let mut store = store("average", None);
+
+        store.extend(vec![
+            (OrderedFloat(1.0), vec![1.0, f32::NAN]),
+            (OrderedFloat(1.0), vec![f32::NAN, 2.0]),
+            (OrderedFloat(1.0), vec![3.0, 4.0]),
+        ]);
+
+        assert_eq!(values(&store, 1.0), vec![vec![2.0, 3.0]]);
+    }
+}
@@ -22,6 +22,13 @@ pub enum Orientation {
     Vertical,
 }
 
+/// Which part of the text is placed at the given horizontal coordinate.
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
 impl Drop for Text {
     fn drop(&mut self) {
         unsafe {
@@ -107,6 +114,7 @@ pub fn generate_text(
     height: u32,
     color: renderer::Color,
     orientation: Orientation,
+    alignment: Alignment,
 ) -> Text {
     let scale = Scale::uniform(scale);
     let v_metrics = font.v_metrics(scale);
@@ -169,6 +177,12 @@ pub fn generate_text(
         }
     };
 
+    let cx = match alignment {
+        Alignment::Left => cx + rect_width / 2.0,
+        Alignment::Center => cx,
+        Alignment::Right => cx - rect_width / 2.0,
+    };
+
     let bl = Vertex {
         x: cx - rect_width / 2.0,
         y: cy - rect_height / 2.0,
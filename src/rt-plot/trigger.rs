@@ -0,0 +1,243 @@
+use super::config::{DataConfig, Slope, TriggerMode};
+use super::store::Store;
+use ordered_float::OrderedFloat;
+use std::collections::VecDeque;
+
+/// Finds edges of a channel and positions the plot on them, instead of letting it roll with the
+/// wall clock. Times are in grid units.
+pub struct Trigger {
+    channel: usize,
+    mode: Option<TriggerMode>,
+    // In the calibrated units of the channel.
+    level: f32,
+    slope: Slope,
+    holdoff: f32,
+    pre_trigger: f32,
+
+    // The last sample searched for edges, and the value of the channel in it.
+    searched: Option<(f32, f32)>,
+    last_edge: Option<f32>,
+    // Edges that can't be shown yet, because the rest of the plot after them hasn't arrived.
+    pending: VecDeque<f32>,
+    shown: Option<f32>,
+    // Where the plot stays in normal and single mode until the first edge.
+    armed: Option<f64>,
+    free_running: bool,
+}
+
+impl Trigger {
+    pub fn new(data_config: &DataConfig) -> Trigger {
+        let seconds_per_grid_unit = data_config.grid.time.seconds_per_division;
+
+        let trigger = data_config.trigger.as_ref();
+        Trigger {
+            channel: trigger
+                .and_then(|trigger| data_config.channel_index(&trigger.channel))
+                .unwrap_or(0),
+            mode: trigger.map(|trigger| trigger.mode),
+            level: trigger.map_or(0.0, |trigger| trigger.level),
+            slope: trigger.map_or(Slope::Rising, |trigger| trigger.slope),
+            holdoff: trigger.map_or(0.0, |trigger| trigger.holdoff / seconds_per_grid_unit),
+            pre_trigger: trigger.map_or(0.5, |trigger| trigger.pre_trigger),
+            searched: None,
+            last_edge: None,
+            pending: VecDeque::new(),
+            shown: None,
+            armed: None,
+            free_running: false,
+        }
+    }
+
+    /// Forgets every edge found so far, so that single mode can trigger again.
+    pub fn arm(&mut self) {
+        self.searched = None;
+        self.last_edge = None;
+        self.pending.clear();
+        self.shown = None;
+        self.armed = None;
+    }
+
+    pub fn mode(&self) -> Option<TriggerMode> {
+        self.mode
+    }
+
+    /// Sets the mode, or turns the trigger off with `None`.
+    pub fn set_mode(&mut self, mode: Option<TriggerMode>) {
+        self.mode = mode;
+        self.arm();
+    }
+
+    // The level is changed in tenths of a division of the channel.
+    fn level_step(&self, data_config: &DataConfig) -> f32 {
+        data_config.y[self.channel].raw_per_division / 10.0
+    }
+
+    /// Raises or lowers the level by a tenth of a division.
+    pub fn step_level(&mut self, up: bool, data_config: &DataConfig) {
+        let step = self.level_step(data_config);
+        let steps = (self.level / step).round() + if up { 1.0 } else { -1.0 };
+
+        self.level = steps * step;
+        self.arm();
+    }
+
+    pub fn slope(&self) -> Slope {
+        self.slope
+    }
+
+    pub fn set_slope(&mut self, slope: Slope) {
+        self.slope = slope;
+        self.arm();
+    }
+
+    // Time at which the channel crosses the level between two samples, if it does so in the
+    // direction of the slope. It's interpolated so that the plot doesn't jitter by a sample.
+    fn crossing(&self, (t0, v0): (f32, f32), (t1, v1): (f32, f32), level: f32) -> Option<f32> {
+        let rising = v0 < level && v1 >= level;
+        let falling = v0 > level && v1 <= level;
+
+        let crosses = match self.slope {
+            Slope::Rising => rising,
+            Slope::Falling => falling,
+            Slope::Either => rising || falling,
+        };
+
+        match crosses {
+            true => Some(t0 + (level - v0) / (v1 - v0) * (t1 - t0)),
+            false => None,
+        }
+    }
+
+    /// Searches the samples up to `end` for new edges and returns where the right edge of the plot
+    /// should be, or `None` if the plot should roll freely. `span` is the width of the plot.
+    pub fn update(
+        &mut self,
+        data: &Store,
+        data_config: &DataConfig,
+        end: f64,
+        span: f32,
+    ) -> Option<f64> {
+        let mode = self.mode?;
+        let end_time = end as f32;
+        let post_trigger = (1.0 - self.pre_trigger) * span;
+
+        // Seeking back in a replay starts over.
+        if let Some((time, _)) = self.searched {
+            if time >= end_time {
+                self.arm();
+            }
+        }
+
+        // A single sweep stays on screen until the trigger is armed again.
+        let stopped = mode == TriggerMode::Single && self.shown.is_some();
+
+        if !stopped {
+            let y = &data_config.y[self.channel];
+            let level = (self.level - y.raw_offset) / y.raw_per_division;
+
+            // Only the visible part of the history is searched the first time.
+            let start = match self.searched {
+                Some((time, _)) => time,
+                None => end_time - span,
+            };
+
+            for (&time, values) in data.range(OrderedFloat(start)..OrderedFloat(end_time)) {
+                let sample = (time.into_inner(), values[self.channel]);
+
+                match self.searched {
+                    Some((previous, _)) if previous >= sample.0 => continue,
+                    // Gaps in the channel aren't edges.
+                    Some(previous) if !previous.1.is_nan() && !sample.1.is_nan() => {
+                        if let Some(edge) = self.crossing(previous, sample, level) {
+                            let held_off = match self.last_edge {
+                                Some(last_edge) => edge < last_edge + self.holdoff,
+                                None => false,
+                            };
+
+                            if !held_off {
+                                self.pending.push_back(edge);
+                                self.last_edge = Some(edge);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                self.searched = Some(sample);
+            }
+
+            // Show the newest edge that has the whole plot after it.
+            while let Some(&edge) = self.pending.front() {
+                if edge + post_trigger > end_time {
+                    break;
+                }
+
+                self.pending.pop_front();
+                self.shown = Some(edge);
+
+                if mode == TriggerMode::Single {
+                    self.pending.clear();
+                    break;
+                }
+            }
+        }
+
+        // In auto mode, the plot rolls freely if there were no edges for a whole plot.
+        self.free_running = match (self.shown, mode) {
+            (Some(edge), TriggerMode::Auto) => end_time - (edge + post_trigger) > span,
+            (None, TriggerMode::Auto) => true,
+            _ => false,
+        };
+
+        match (self.shown, self.free_running) {
+            (_, true) => None,
+            (Some(edge), false) => Some((edge + post_trigger) as f64),
+            (None, false) => Some(*self.armed.get_or_insert(end)),
+        }
+    }
+
+    /// Describes the trigger and whether it's waiting for an edge, or `None` if it's off.
+    pub fn summary(&self, data_config: &DataConfig) -> Option<String> {
+        let mode = self.mode?;
+        let y = &data_config.y[self.channel];
+
+        let name = y
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("y{}", self.channel + 1));
+        let unit = y
+            .unit
+            .as_deref()
+            .map_or(String::new(), |unit| format!(" {}", unit));
+        let slope = match self.slope {
+            Slope::Rising => "rising",
+            Slope::Falling => "falling",
+            Slope::Either => "either",
+        };
+        let mode_name = match mode {
+            TriggerMode::Auto => "auto",
+            TriggerMode::Normal => "normal",
+            TriggerMode::Single => "single",
+        };
+        let state = match (mode, self.shown, self.free_running) {
+            (_, _, true) => "free running",
+            (TriggerMode::Single, Some(_), _) => "stopped",
+            (_, Some(_), _) => "triggered",
+            (_, None, _) => "armed",
+        };
+
+        // Enough decimals for the steps of the level.
+        let decimals = (-self.level_step(data_config).log10().floor()).max(0.0) as usize;
+
+        Some(format!(
+            "Trigger ({}): {} edge of {} at {:.decimals$}{}, {}",
+            mode_name,
+            slope,
+            name,
+            self.level,
+            unit,
+            state,
+            decimals = decimals
+        ))
+    }
+}
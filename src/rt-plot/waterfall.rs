@@ -0,0 +1,459 @@
+use super::config::{ColorMap, DataConfig, NamedColorMap, Window};
+use super::spectrum;
+use super::store::Store;
+use gl::types::{GLint, GLsizeiptr, GLuint, GLvoid};
+use ordered_float::OrderedFloat;
+use rustfft::FftPlanner;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Levels are rounded up to this many dB for the highest color, unless it's set.
+const DB_STEP: f32 = 10.0;
+
+fn color_stops(color_map: &ColorMap) -> Vec<[u8; 3]> {
+    match color_map {
+        ColorMap::Named(NamedColorMap::Grayscale) => vec![[0, 0, 0], [255, 255, 255]],
+        ColorMap::Named(NamedColorMap::Viridis) => vec![
+            [68, 1, 84],
+            [59, 82, 139],
+            [33, 145, 140],
+            [94, 201, 98],
+            [253, 231, 37],
+        ],
+        ColorMap::Named(NamedColorMap::Inferno) => vec![
+            [0, 0, 4],
+            [87, 16, 110],
+            [188, 55, 84],
+            [249, 142, 9],
+            [252, 255, 164],
+        ],
+        ColorMap::Named(NamedColorMap::Jet) => vec![
+            [0, 0, 131],
+            [0, 60, 170],
+            [5, 255, 255],
+            [255, 255, 0],
+            [250, 0, 0],
+            [128, 0, 0],
+        ],
+        ColorMap::Colors(colors) => colors.clone(),
+    }
+}
+
+// Interpolates between the colors for a level from 0 to 1.
+fn color(stops: &[[u8; 3]], level: f32) -> [u8; 3] {
+    let position = level.max(0.0).min(1.0) * (stops.len() - 1) as f32;
+    let i = (position.floor() as usize).min(stops.len() - 2);
+    let fraction = position - i as f32;
+
+    let mut color = [0; 3];
+    for c in 0..3 {
+        color[c] = (stops[i][c] as f32 * (1.0 - fraction) + stops[i + 1][c] as f32 * fraction)
+            .round() as u8;
+    }
+
+    color
+}
+
+// The levels of the visible columns of a waterfall, kept apart from its image. Every column is the
+// spectrum of the samples before its end, in dB relative to one unit of the channel.
+struct Spectrogram {
+    points: usize,
+    columns: usize,
+    window: Window,
+    channel: usize,
+    planner: FftPlanner<f32>,
+
+    // Levels of the columns by their index, which is the time of their start divided by their
+    // width, so that they stay the same as the plot scrolls. Columns without samples are empty.
+    levels: BTreeMap<i64, Vec<f32>>,
+    // Columns that end before the newest sample, which won't change anymore.
+    complete: BTreeSet<i64>,
+    // In grid units.
+    column_width: f32,
+    // Spacing of the frequencies in Hz.
+    resolution: f32,
+}
+
+impl Spectrogram {
+    fn new(data_config: &DataConfig) -> Spectrogram {
+        Spectrogram {
+            points: data_config.waterfall.points,
+            columns: data_config.waterfall.columns,
+            window: data_config.spectrum.window,
+            channel: 0,
+            planner: FftPlanner::new(),
+            levels: BTreeMap::new(),
+            complete: BTreeSet::new(),
+            column_width: 0.0,
+            resolution: 0.0,
+        }
+    }
+
+    // Levels of a column from the last samples before its end, if any of them are in the column.
+    fn compute_column(&mut self, data: &Store, data_config: &DataConfig, column: i64) -> Vec<f32> {
+        let y = &data_config.y[self.channel];
+        let (start, end) = (
+            column as f32 * self.column_width,
+            (column + 1) as f32 * self.column_width,
+        );
+
+        let mut samples: Vec<(f32, f32)> = data
+            .range(OrderedFloat(f32::NEG_INFINITY)..OrderedFloat(end))
+            .rev()
+            .map(|(time, values)| {
+                (
+                    time.into_inner(),
+                    values[self.channel] * y.raw_per_division + y.raw_offset,
+                )
+            })
+            .filter(|(_, value)| !value.is_nan())
+            .take(self.points)
+            .collect();
+        samples.reverse();
+
+        // Spectra of fewer samples, like at the start of the data, would be mostly noise.
+        if samples.len() < self.points / 2 || samples[samples.len() - 1].0 < start {
+            return vec![];
+        }
+
+        match spectrum::analyze(
+            &mut self.planner,
+            &samples,
+            self.points,
+            self.window,
+            data_config.grid.time.seconds_per_division,
+        ) {
+            Some((amplitudes, resolution)) => {
+                self.resolution = resolution;
+                amplitudes.into_iter().map(spectrum::to_db).collect()
+            }
+            None => vec![],
+        }
+    }
+
+    // Computes the columns that became visible and drops the ones that aren't anymore. Returns the
+    // index of the leftmost column.
+    fn update(
+        &mut self,
+        data: &Store,
+        data_config: &DataConfig,
+        end: f64,
+        span: f32,
+        channel: usize,
+        window: Window,
+    ) -> i64 {
+        let column_width = span / self.columns as f32;
+        if column_width != self.column_width || channel != self.channel || window != self.window {
+            self.levels.clear();
+            self.complete.clear();
+            self.column_width = column_width;
+            self.channel = channel;
+            self.window = window;
+        }
+
+        // The column at the right edge isn't complete yet, and is left out.
+        let last = (end as f32 / column_width).floor() as i64;
+        let first = last - self.columns as i64;
+
+        // The right edge moves back when a replay seeks back or the trigger fires, so columns are
+        // dropped on both sides.
+        self.levels = self.levels.split_off(&first);
+        self.levels.split_off(&last);
+        self.complete = self.complete.split_off(&first);
+        self.complete.split_off(&last);
+
+        // Samples arrive later than the wall clock says, so columns the data hasn't reached the end
+        // of yet are computed again every frame, until it has.
+        let newest = data
+            .last()
+            .map_or(f32::NEG_INFINITY, |time| time.into_inner());
+        for column in first..last {
+            if self.complete.contains(&column) {
+                continue;
+            }
+
+            let levels = match column as f32 * column_width > newest {
+                true => vec![],
+                false => self.compute_column(data, data_config, column),
+            };
+            self.levels.insert(column, levels);
+
+            if (column + 1) as f32 * column_width <= newest {
+                self.complete.insert(column);
+            }
+        }
+
+        first
+    }
+}
+
+/// A spectrogram of a channel, drawn as an image that scrolls along with the time axis of the
+/// plot. Every column is the spectrum of the samples before its end, in dB relative to one unit of
+/// the channel.
+pub struct Waterfall {
+    spectrogram: Spectrogram,
+    colors: Vec<[u8; 3]>,
+    max_db: Option<f32>,
+    dynamic_range: f32,
+    // Level in dB of the highest color.
+    top: f32,
+
+    vertices: usize,
+    vao: GLuint,
+    vbo: GLuint,
+    texture: GLuint,
+}
+
+impl Drop for Waterfall {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &mut self.vbo);
+            gl::DeleteVertexArrays(1, &mut self.vao);
+            gl::DeleteTextures(1, &mut self.texture);
+        }
+    }
+}
+
+impl Waterfall {
+    pub fn new(data_config: &DataConfig) -> Waterfall {
+        let waterfall = &data_config.waterfall;
+
+        let (mut vao, mut vbo, mut texture): (GLuint, GLuint, GLuint) = (0, 0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::GenTextures(1, &mut texture);
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                0,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                (4 * std::mem::size_of::<f32>()) as GLint,
+                std::ptr::null(),
+            );
+
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                (4 * std::mem::size_of::<f32>()) as GLint,
+                (2 * std::mem::size_of::<f32>()) as *const core::ffi::c_void,
+            );
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        Waterfall {
+            spectrogram: Spectrogram::new(data_config),
+            colors: color_stops(&waterfall.color_map),
+            max_db: waterfall.max_db,
+            dynamic_range: waterfall.dynamic_range,
+            top: 0.0,
+            vertices: 0,
+            vao,
+            vbo,
+            texture,
+        }
+    }
+
+    /// Computes the columns that became visible, and updates the image. `end` is the right edge of
+    /// the plot and `span` its width, in grid units. The window function is the one the spectrum
+    /// currently uses.
+    pub fn update(
+        &mut self,
+        data: &Store,
+        data_config: &DataConfig,
+        end: f64,
+        span: f32,
+        channel: usize,
+        window: Window,
+    ) {
+        let first = self
+            .spectrogram
+            .update(data, data_config, end, span, channel, window);
+        let spectrogram = &self.spectrogram;
+        let (columns, column_width) = (spectrogram.columns, spectrogram.column_width);
+
+        // The constant part is left out of the highest level, since it's usually far above the
+        // rest of the spectrum.
+        let highest = spectrogram
+            .levels
+            .values()
+            .flat_map(|levels| levels.iter().skip(1))
+            .cloned()
+            .fold(f32::NEG_INFINITY, f32::max);
+        self.top = match (self.max_db, highest.is_finite()) {
+            (Some(max_db), _) => max_db,
+            (None, true) => (highest / DB_STEP).ceil() * DB_STEP,
+            (None, false) => 0.0,
+        };
+
+        // Every column is a column of pixels, with the lowest frequencies at the bottom.
+        let rows = spectrogram.points / 2 + 1;
+        let mut pixels = vec![0u8; columns * rows * 4];
+        for (&column, levels) in &spectrogram.levels {
+            let x = (column - first) as usize;
+            for (row, &level) in levels.iter().enumerate() {
+                let [r, g, b] = color(&self.colors, 1.0 - (self.top - level) / self.dynamic_range);
+                let pixel = (row * columns + x) * 4;
+                pixels[pixel..pixel + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+
+        // The image spans the columns horizontally in grid units, and the height of the grid.
+        let (left, right) = (
+            first as f32 * column_width,
+            (first + columns as i64) as f32 * column_width,
+        );
+        let vertices: [f32; 24] = [
+            left, -1.0, 0.0, 0.0, //
+            left, 1.0, 0.0, 1.0, //
+            right, 1.0, 1.0, 1.0, //
+            right, 1.0, 1.0, 1.0, //
+            right, -1.0, 1.0, 0.0, //
+            left, -1.0, 0.0, 0.0, //
+        ];
+        self.vertices = 6;
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (vertices.len() * std::mem::size_of::<f32>()) as GLsizeiptr,
+                vertices.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as i32,
+                columns as i32,
+                rows as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const GLvoid,
+            );
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    pub fn draw(&self) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::BindVertexArray(self.vao);
+
+            gl::DrawArrays(gl::TRIANGLES, 0, self.vertices as i32);
+
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    /// Describes the frequency axis, which goes from zero up to half the sample rate.
+    pub fn frequency_label(&self) -> String {
+        format!(
+            "0 to {:.0} Hz, {:.1} Hz resolution",
+            self.spectrogram.resolution * (self.spectrogram.points / 2) as f32,
+            self.spectrogram.resolution
+        )
+    }
+
+    /// Describes the range of levels of the colors.
+    pub fn level_label(&self, data_config: &DataConfig) -> String {
+        let channel = self.spectrogram.channel;
+        let y = &data_config.y[channel];
+        let name = y
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("y{}", channel + 1));
+
+        let range = format!("{} dB to {} dB", self.top - self.dynamic_range, self.top);
+        match &y.unit {
+            Some(unit) => format!("{}: {} re 1 {}", name, range, unit),
+            None => format!("{}: {}", name, range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recomputes_columns_after_moving_back() {
+        let data_config: DataConfig = toml::from_str(
+            r#"
+            [waterfall]
+            points = 16
+            columns = 4
+
+            [grid]
+            label = ""
+            [grid.time]
+            divisions = 10
+            seconds_per_division = 1.0
+            raw_per_second = 1.0
+            label = ""
+            [grid.data]
+            divisions = 4
+            zero_shift = 0.0
+            label = ""
+
+            [[y]]
+            raw_offset = 0.0
+            raw_per_division = 1.0
+            "#,
+        )
+        .unwrap();
+
+        let mut data = Store::new(&data_config);
+        data.extend(
+            (0..200)
+                .map(|i| {
+                    let time = i as f32 / 10.0;
+                    (OrderedFloat(time), vec![(time * 3.0).sin()])
+                })
+                .collect(),
+        );
+
+        let mut spectrogram = Spectrogram::new(&data_config);
+        let window = data_config.spectrum.window;
+        assert_eq!(
+            spectrogram.update(&data, &data_config, 10.0, 4.0, 0, window),
+            6
+        );
+        // Like seeking back in a replay.
+        assert_eq!(
+            spectrogram.update(&data, &data_config, 8.0, 4.0, 0, window),
+            4
+        );
+        assert_eq!(
+            spectrogram.update(&data, &data_config, 10.0, 4.0, 0, window),
+            6
+        );
+
+        assert_eq!(
+            spectrogram.levels.keys().cloned().collect::<Vec<i64>>(),
+            vec![6, 7, 8, 9]
+        );
+        assert!(spectrogram.levels.values().all(|levels| levels.len() == 9));
+    }
+}